@@ -2,6 +2,7 @@ use crate::backing_alloc::BackingAllocation;
 use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
 use core::marker::PhantomData;
 use core::mem::MaybeUninit;
+use core::ptr;
 
 pub struct AlignedRawSlice<'a, T> {
     slice: *const [T],
@@ -149,4 +150,107 @@ impl<'buf, T> AlignedMutRawSlice<'buf, T> {
     pub const unsafe fn as_mut_slice(&mut self) -> &mut [T] {
         unsafe { &mut *self.as_mut_raw_slice() }
     }
+
+    #[inline]
+    #[must_use]
+    pub const fn reborrow(&mut self) -> AlignedMutRawSlice<'_, T> {
+        AlignedMutRawSlice {
+            slice: self.slice,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Divides the slice into two at index `mid`. Both halves stay aligned for `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at(self, mid: usize) -> (Self, Self) {
+        match self.split_at_checked(mid) {
+            Some(halves) => halves,
+            None => panic!("mid > len"),
+        }
+    }
+
+    /// Divides the slice into two at index `mid`, returning `None` if `mid > len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at_checked(self, mid: usize) -> Option<(Self, Self)> {
+        let len = self.len();
+        if mid > len {
+            return None;
+        }
+
+        let data = self.slice.cast::<T>();
+        // Safety: mid is in bounds of the slice.
+        let tail = unsafe { data.add(mid) };
+
+        let head = AlignedMutRawSlice {
+            slice: ptr::slice_from_raw_parts_mut(data, mid),
+            _marker: PhantomData,
+        };
+        let tail = AlignedMutRawSlice {
+            slice: ptr::slice_from_raw_parts_mut(tail, len - mid),
+            _marker: PhantomData,
+        };
+
+        Some((head, tail))
+    }
+
+    /// Splits the slice into disjoint chunks of `chunk_size` elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    #[inline]
+    #[must_use]
+    pub const fn chunks_exact(self, chunk_size: usize) -> ChunksExact<'buf, T> {
+        assert!(chunk_size != 0, "chunk size must be non-zero");
+        ChunksExact { rest: self, chunk_size }
+    }
+}
+
+pub struct ChunksExact<'buf, T> {
+    rest: AlignedMutRawSlice<'buf, T>,
+    chunk_size: usize,
+}
+
+impl<'buf, T> ChunksExact<'buf, T> {
+    /// Returns the elements left over after all whole chunks, consuming the iterator.
+    #[inline]
+    #[must_use]
+    pub const fn into_remainder(self) -> AlignedMutRawSlice<'buf, T> {
+        let whole = self.rest.len() - self.rest.len() % self.chunk_size;
+        let (_, remainder) = self.rest.split_at(whole);
+        remainder
+    }
 }
+
+impl<'buf, T> Iterator for ChunksExact<'buf, T> {
+    type Item = AlignedMutRawSlice<'buf, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.len() < self.chunk_size {
+            return None;
+        }
+
+        let rest = AlignedMutRawSlice {
+            slice: self.rest.slice,
+            _marker: PhantomData,
+        };
+        let (chunk, rest) = rest.split_at(self.chunk_size);
+        self.rest = rest;
+        Some(chunk)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.rest.len() / self.chunk_size;
+        (n, Some(n))
+    }
+}
+
+impl<T> ExactSizeIterator for ChunksExact<'_, T> {}
@@ -0,0 +1,214 @@
+use crate::const_allocator_shared::AllocError;
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::task::{Context, Poll, Waker};
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// A queue entry living inside a pinned [`AllocFuture`].
+struct Waiter {
+    prev: Cell<Option<NonNull<Self>>>,
+    next: Cell<Option<NonNull<Self>>>,
+    waker: Cell<Option<Waker>>,
+    linked: Cell<bool>,
+    _pinned: PhantomPinned,
+}
+
+/// Wraps an [`ExclusiveAllocator`] so tasks can wait for memory instead of failing.
+///
+/// Waiting tasks form a FIFO queue: once anyone is waiting, new requests line up behind
+/// them even if they would fit, so a large request can't be starved by small ones. Only
+/// the task at the front is woken when memory is freed. The queue is intrusive, so
+/// waiting doesn't allocate.
+///
+/// The wrapper is meant for single-threaded executors and isn't `Sync`.
+pub struct AsyncAllocator<A> {
+    inner: UnsafeCell<A>,
+    head: Cell<Option<NonNull<Waiter>>>,
+    tail: Cell<Option<NonNull<Waiter>>>,
+}
+
+impl<A: ExclusiveAllocator> AsyncAllocator<A> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner: UnsafeCell::new(inner),
+            head: Cell::new(None),
+            tail: Cell::new(None),
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.inner.into_inner()
+    }
+
+    /// Returns whether any task is waiting for memory.
+    #[inline]
+    #[must_use]
+    pub const fn has_waiters(&self) -> bool {
+        self.head.get().is_some()
+    }
+
+    #[inline]
+    fn inner_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Safety: the wrapper isn't `Sync` and the inner allocator never calls back into it.
+        unsafe { (*self.inner.get()).alloc_exclusive(layout) }
+    }
+
+    /// Allocates without waiting. Fails while other tasks are waiting, to keep their turn.
+    #[inline]
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.has_waiters() {
+            return Err(AllocError);
+        }
+        self.inner_alloc(layout)
+    }
+
+    /// Returns a future resolving to a block for `layout`, waiting for frees as needed.
+    ///
+    /// Dropping the future before it completes removes it from the queue. A layout the
+    /// inner allocator can never satisfy waits forever.
+    #[inline]
+    pub const fn alloc_async(&self, layout: Layout) -> AllocFuture<'_, A> {
+        AllocFuture {
+            alloc: self,
+            layout,
+            waiter: Waiter {
+                prev: Cell::new(None),
+                next: Cell::new(None),
+                waker: Cell::new(None),
+                linked: Cell::new(false),
+                _pinned: PhantomPinned,
+            },
+        }
+    }
+
+    /// Frees a block and wakes the task at the front of the queue.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this allocator with the same `layout`, and must not
+    /// be used afterwards.
+    #[inline]
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { (*self.inner.get()).dealloc_exclusive(ptr, layout) };
+        self.wake_front();
+    }
+
+    #[inline]
+    fn wake_front(&self) {
+        if let Some(head) = self.head.get() {
+            let head = unsafe { head.as_ref() };
+            if let Some(waker) = head.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn push_back(&self, waiter: &Waiter) {
+        let node = NonNull::from(waiter);
+        waiter.prev.set(self.tail.get());
+        waiter.next.set(None);
+        waiter.linked.set(true);
+
+        match self.tail.get() {
+            Some(tail) => unsafe { tail.as_ref() }.next.set(Some(node)),
+            None => self.head.set(Some(node)),
+        }
+        self.tail.set(Some(node));
+    }
+
+    fn unlink(&self, waiter: &Waiter) {
+        let prev = waiter.prev.take();
+        let next = waiter.next.take();
+        waiter.linked.set(false);
+
+        match prev {
+            Some(prev) => unsafe { prev.as_ref() }.next.set(next),
+            None => self.head.set(next),
+        }
+        match next {
+            Some(next) => unsafe { next.as_ref() }.prev.set(prev),
+            None => self.tail.set(prev),
+        }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: ExclusiveAllocator> Allocator for AsyncAllocator<A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.try_alloc(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.dealloc(ptr, layout) };
+    }
+}
+
+/// The future returned by [`AsyncAllocator::alloc_async`].
+#[must_use = "futures do nothing unless polled"]
+pub struct AllocFuture<'a, A: ExclusiveAllocator> {
+    alloc: &'a AsyncAllocator<A>,
+    layout: Layout,
+    waiter: Waiter,
+}
+
+impl<A: ExclusiveAllocator> Future for AllocFuture<'_, A> {
+    type Output = NonNull<[u8]>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the waiter is never moved out, and is unlinked in `Drop` before the
+        // future's memory can be reused.
+        let this = unsafe { self.get_unchecked_mut() };
+        let alloc = this.alloc;
+        let waiter = &this.waiter;
+        let is_front = alloc.head.get() == Some(NonNull::from(waiter));
+
+        if (is_front || (!waiter.linked.get() && !alloc.has_waiters()))
+            && let Ok(block) = alloc.inner_alloc(this.layout)
+        {
+            if waiter.linked.get() {
+                alloc.unlink(waiter);
+            }
+            // whatever freed memory for us may have freed enough for the next one too
+            alloc.wake_front();
+            return Poll::Ready(block);
+        }
+
+        if !waiter.linked.get() {
+            alloc.push_back(waiter);
+        }
+        waiter.waker.set(Some(cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+impl<A: ExclusiveAllocator> Drop for AllocFuture<'_, A> {
+    #[inline]
+    fn drop(&mut self) {
+        let waiter = &self.waiter;
+        if waiter.linked.get() {
+            let was_front = self.alloc.head.get() == Some(NonNull::from(waiter));
+            self.alloc.unlink(waiter);
+
+            // a cancelled front waiter hands its turn on
+            if was_front {
+                self.alloc.wake_front();
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::const_allocator_shared::cast_raw_slice;
 use crate::const_allocator_shared::cast_raw_slice_mut;
+use crate::const_allocator_shared::next_aligned_addr;
 use core::marker::PhantomData;
 use core::mem::MaybeUninit;
+use core::mem::align_of;
+use core::ptr;
 use core::slice;
 
 #[repr(transparent)]
@@ -10,18 +13,15 @@ pub struct BackingAllocation<'buf> {
     _marker: PhantomData<&'buf mut [u8]>,
 }
 
+// Safety: a backing allocation is unique access to its bytes, like `&mut [u8]`.
+unsafe impl Send for BackingAllocation<'_> {}
+unsafe impl Sync for BackingAllocation<'_> {}
+
 impl<'buf> BackingAllocation<'buf> {
+    /// The contents are left as they are. Allocators fill fresh bytes according to their
+    /// [`PoisonPolicy`](crate::poison::PoisonPolicy) when they first hand them out.
     #[inline]
     pub const fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
-        // overwrite the contents of the slice with 0xAA in debug builds
-        if cfg!(debug_assertions) {
-            let mut i = 0;
-            while i < slice.len() {
-                slice[i] = 0xAA;
-                i += 1;
-            }
-        }
-
         let uninit_slice: &'buf mut [MaybeUninit<u8>] = {
             // Safety: the reborrow comes from a concrete mutable slice,
             // and the lifetime is unchanged. Layout of MaybeUninit<T> is
@@ -34,15 +34,6 @@ impl<'buf> BackingAllocation<'buf> {
 
     #[inline]
     pub const fn from_unique_uninit_slice(slice: &'buf mut [MaybeUninit<u8>]) -> Self {
-        // overwrite the contents of the slice with 0xAA in debug builds
-        if cfg!(debug_assertions) {
-            let mut i = 0;
-            while i < slice.len() {
-                slice[i].write(0xAA);
-                i += 1;
-            }
-        }
-
         BackingAllocation { slice, _marker: PhantomData }
     }
 
@@ -102,4 +93,125 @@ impl<'buf> BackingAllocation<'buf> {
     pub const fn into_inner(self) -> &'buf mut [MaybeUninit<u8>] {
         unsafe { slice::from_raw_parts_mut(self.slice.cast::<MaybeUninit<u8>>(), self.len()) }
     }
+
+    /// Borrows this allocation as a shorter-lived one, so it can be handed to an
+    /// allocator temporarily and used again afterwards.
+    #[inline]
+    #[must_use]
+    pub const fn reborrow(&mut self) -> BackingAllocation<'_> {
+        BackingAllocation { slice: self.slice, _marker: PhantomData }
+    }
+
+    /// Divides the allocation into two at `mid` bytes. The first half contains
+    /// `[0, mid)` and the second half contains `[mid, len)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at(self, mid: usize) -> (Self, Self) {
+        match self.split_at_checked(mid) {
+            Some(halves) => halves,
+            None => panic!("mid > len"),
+        }
+    }
+
+    /// Divides the allocation into two at `mid` bytes, returning `None` if `mid > len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at_checked(self, mid: usize) -> Option<(Self, Self)> {
+        let len = self.len();
+        if mid > len {
+            return None;
+        }
+
+        let data = self.slice.cast::<MaybeUninit<u8>>();
+        // Safety: mid is in bounds of the allocation.
+        let tail = unsafe { data.add(mid) };
+
+        let head = BackingAllocation {
+            slice: ptr::slice_from_raw_parts_mut(data, mid),
+            _marker: PhantomData,
+        };
+        let tail = BackingAllocation {
+            slice: ptr::slice_from_raw_parts_mut(tail, len - mid),
+            _marker: PhantomData,
+        };
+
+        Some((head, tail))
+    }
+
+    /// Divides the allocation at the first offset that is properly aligned for a `T`.
+    /// The first half is the (possibly empty) padding, the second half starts aligned.
+    /// If no aligned offset lies within the allocation, the second half is empty.
+    #[inline]
+    #[must_use]
+    pub fn split_aligned_for<T>(self) -> (Self, Self) {
+        let base = self.as_ptr().addr();
+        let padding = next_aligned_addr(base, align_of::<T>()) - base;
+        let mid = if padding > self.len() { self.len() } else { padding };
+
+        self.split_at(mid)
+    }
+
+    /// Splits the allocation into disjoint chunks of `chunk_size` bytes. The bytes
+    /// that don't fill a whole chunk are available through
+    /// [`ChunksExact::into_remainder`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    #[inline]
+    #[must_use]
+    pub const fn chunks_exact(self, chunk_size: usize) -> ChunksExact<'buf> {
+        assert!(chunk_size != 0, "chunk size must be non-zero");
+        ChunksExact { rest: self, chunk_size }
+    }
 }
+
+pub struct ChunksExact<'buf> {
+    rest: BackingAllocation<'buf>,
+    chunk_size: usize,
+}
+
+impl<'buf> ChunksExact<'buf> {
+    #[inline]
+    #[must_use]
+    pub const fn remainder_len(&self) -> usize {
+        self.rest.len() % self.chunk_size
+    }
+
+    /// Returns the bytes left over after all whole chunks, consuming the iterator.
+    #[inline]
+    #[must_use]
+    pub const fn into_remainder(self) -> BackingAllocation<'buf> {
+        let whole = self.rest.len() - self.remainder_len();
+        let (_, remainder) = self.rest.split_at(whole);
+        remainder
+    }
+}
+
+impl<'buf> Iterator for ChunksExact<'buf> {
+    type Item = BackingAllocation<'buf>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.len() < self.chunk_size {
+            return None;
+        }
+
+        let rest = BackingAllocation { slice: self.rest.slice, _marker: PhantomData };
+        let (chunk, rest) = rest.split_at(self.chunk_size);
+        self.rest = rest;
+        Some(chunk)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.rest.len() / self.chunk_size;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for ChunksExact<'_> {}
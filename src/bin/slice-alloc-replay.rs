@@ -0,0 +1,104 @@
+//! Replays an allocation trace recorded with `slice_alloc::trace` against the allocators
+//! of the crate.
+//!
+//! Usage: `slice-alloc-replay <trace file> [arena bytes]`
+
+#![feature(allocator_api)]
+
+use core::alloc::{Allocator, Layout};
+use core::ptr::NonNull;
+use slice_alloc::const_allocator_shared::AllocError;
+use slice_alloc::experimental_allocator::ExperimentalAllocator;
+use slice_alloc::locked_allocator::{ExclusiveAllocator, LockedAllocator, SpinLock};
+use slice_alloc::slice_allocator::{SingleThreadedSliceAllocator, StackAllocator};
+use slice_alloc::thread_cache::ThreadCachedHeap;
+use slice_alloc::trace::{self, ReplayReport};
+use slice_alloc::unaligned_generic_buffer::UnalignedGenericBuffer;
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+const DEFAULT_ARENA_BYTES: usize = 16 << 20;
+
+/// Lets `replay` drive allocators that only implement [`Allocator`].
+struct Shared<A>(A);
+
+unsafe impl<A: Allocator> ExclusiveAllocator for Shared<A> {
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate(layout).map_err(|_| AllocError)
+    }
+
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.0.deallocate(ptr, layout) };
+    }
+}
+
+fn print_report(name: &str, report: &ReplayReport) {
+    println!(
+        "{name:<32} {:>10} {:>10} {:>14} {:>14} {:>13.1}%",
+        report.ops,
+        report.failures,
+        report.peak_live_bytes,
+        report.footprint_bytes,
+        report.fragmentation() * 100.0,
+    );
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: slice-alloc-replay <trace file> [arena bytes]");
+        return ExitCode::FAILURE;
+    };
+    let arena_bytes = match args.next().map(|arg| arg.parse::<usize>()) {
+        None => DEFAULT_ARENA_BYTES,
+        Some(Ok(bytes)) => bytes,
+        Some(Err(err)) => {
+            eprintln!("invalid arena size: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("cannot read {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let records: Vec<_> = trace::parse_trace(&bytes).collect();
+    if records.len() * trace::RECORD_SIZE != bytes.len() {
+        eprintln!("warning: trace is truncated or corrupt after {} records", records.len());
+    }
+
+    println!(
+        "{:<32} {:>10} {:>10} {:>14} {:>14} {:>14}",
+        "allocator", "ops", "failures", "peak live", "footprint", "fragmentation"
+    );
+
+    let mut arena = vec![0u8; arena_bytes];
+
+    // Safety: the allocator is only used from this thread.
+    let mut slice = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut arena) };
+    print_report("SingleThreadedSliceAllocator", &trace::replay(&mut slice, records.iter().copied()));
+
+    let mut stack = StackAllocator::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_slice(&mut arena));
+    print_report("StackAllocator", &trace::replay(&mut stack, records.iter().copied()));
+
+    let mut experimental = ExperimentalAllocator::from_unique_slice(&mut arena);
+    print_report("ExperimentalAllocator", &trace::replay(&mut experimental, records.iter().copied()));
+
+    let mut locked = Shared(LockedAllocator::new(
+        SpinLock::new(),
+        ExperimentalAllocator::from_unique_slice(&mut arena),
+    ));
+    print_report(
+        "LockedAllocator<Experimental>",
+        &trace::replay(&mut locked, records.iter().copied()),
+    );
+
+    let mut cached = Shared(ThreadCachedHeap::from_unique_slice(&mut arena));
+    print_report("ThreadCachedHeap", &trace::replay(&mut cached, records.iter().copied()));
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,237 @@
+use crate::const_allocator_shared::AllocError;
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::AllocError as StdAllocError;
+use core::alloc::Allocator;
+use core::alloc::Layout;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Why a [`Budgeted`] allocation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetError {
+    /// This budget or one of its ancestors is out of bytes or allocations.
+    QuotaExceeded,
+    /// The budgets allowed it, but the underlying allocator failed.
+    OutOfMemory,
+}
+
+enum Source<'parent, A> {
+    Root(A),
+    Child(&'parent Budgeted<'parent, A>),
+}
+
+/// A node in a tree of allocation budgets, each with a byte limit and a live allocation
+/// count limit.
+///
+/// The root wraps the allocator. Children created with [`child`](Self::child) allocate
+/// from the same allocator, and every allocation is charged to the child and all of its
+/// ancestors, so children draw from their parent's budget.
+///
+/// A root budget also implements [`ExclusiveAllocator`] when `A` does, so it can wrap an
+/// allocator such as [`ExperimentalAllocator`](crate::experimental_allocator::ExperimentalAllocator)
+/// directly. Children share the root allocator, so they need `A` to be an [`Allocator`].
+pub struct Budgeted<'parent, A> {
+    source: Source<'parent, A>,
+    max_bytes: usize,
+    max_allocs: usize,
+    bytes: AtomicUsize,
+    allocs: AtomicUsize,
+}
+
+impl<A> Budgeted<'_, A> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A, max_bytes: usize, max_allocs: usize) -> Self {
+        Budgeted::from_source(Source::Root(inner), max_bytes, max_allocs)
+    }
+}
+
+impl<'parent, A> Budgeted<'parent, A> {
+    #[inline]
+    const fn from_source(source: Source<'parent, A>, max_bytes: usize, max_allocs: usize) -> Self {
+        Budgeted {
+            source,
+            max_bytes,
+            max_allocs,
+            bytes: AtomicUsize::new(0),
+            allocs: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the amount of bytes currently allocated through this budget and its children.
+    #[inline]
+    #[must_use]
+    pub fn used_bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of live allocations made through this budget and its children.
+    #[inline]
+    #[must_use]
+    pub fn live_allocs(&self) -> usize {
+        self.allocs.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_allocs(&self) -> usize {
+        self.max_allocs
+    }
+
+    #[inline]
+    const fn parent(&self) -> Option<&Self> {
+        match self.source {
+            Source::Root(_) => None,
+            Source::Child(parent) => Some(parent),
+        }
+    }
+
+    /// Charges this node alone, leaving it untouched on failure.
+    #[inline]
+    fn charge_node(&self, size: usize) -> bool {
+        let bytes_ok = self
+            .bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bytes| {
+                bytes.checked_add(size).filter(|&bytes| bytes <= self.max_bytes)
+            })
+            .is_ok();
+        if !bytes_ok {
+            return false;
+        }
+
+        let allocs_ok = self
+            .allocs
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocs| {
+                (allocs < self.max_allocs).then_some(allocs + 1)
+            })
+            .is_ok();
+        if !allocs_ok {
+            self.bytes.fetch_sub(size, Ordering::Relaxed);
+        }
+        allocs_ok
+    }
+
+    #[inline]
+    fn refund_node(&self, size: usize) {
+        self.bytes.fetch_sub(size, Ordering::Relaxed);
+        self.allocs.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Refunds this node and its ancestors up to, but not including, `stop`.
+    #[inline]
+    fn refund_until(&self, size: usize, stop: Option<&Self>) {
+        let mut node = Some(self);
+        while let Some(current) = node {
+            if stop.is_some_and(|stop| ptr::eq(current, stop)) {
+                break;
+            }
+            current.refund_node(size);
+            node = current.parent();
+        }
+    }
+
+    /// Charges this node and all of its ancestors, or none of them.
+    #[inline]
+    fn charge(&self, size: usize) -> Result<(), BudgetError> {
+        let mut node = Some(self);
+        while let Some(current) = node {
+            if !current.charge_node(size) {
+                self.refund_until(size, Some(current));
+                return Err(BudgetError::QuotaExceeded);
+            }
+            node = current.parent();
+        }
+        Ok(())
+    }
+}
+
+impl<'parent, A: Allocator> Budgeted<'parent, A> {
+    /// Creates a budget drawing from this one.
+    ///
+    /// The child's limits may add up to more than the parent's; the parent's limits still
+    /// apply to the sum of its children.
+    #[inline]
+    #[must_use]
+    pub const fn child(&'parent self, max_bytes: usize, max_allocs: usize) -> Self {
+        Budgeted::from_source(Source::Child(self), max_bytes, max_allocs)
+    }
+
+    #[inline]
+    const fn root_allocator(&self) -> &A {
+        let mut node = self;
+        loop {
+            match node.source {
+                Source::Root(ref inner) => return inner,
+                Source::Child(parent) => node = parent,
+            }
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`BudgetError::QuotaExceeded`] if this budget or an ancestor can't cover
+    /// the allocation, and [`BudgetError::OutOfMemory`] if the allocator fails.
+    #[inline]
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, BudgetError> {
+        self.charge(layout.size())?;
+
+        self.root_allocator().allocate(layout).map_err(|_| {
+            self.refund_until(layout.size(), None);
+            BudgetError::OutOfMemory
+        })
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated through this budget with the same `layout`, and must
+    /// not be used afterwards.
+    #[inline]
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.root_allocator().deallocate(ptr, layout) };
+        self.refund_until(layout.size(), None);
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Budgeted<'_, A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.try_alloc(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.dealloc(ptr, layout) };
+    }
+}
+
+unsafe impl<A: ExclusiveAllocator> ExclusiveAllocator for Budgeted<'_, A> {
+    #[inline]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.charge(layout.size()).map_err(|_| AllocError)?;
+
+        let block = match self.source {
+            Source::Root(ref mut inner) => inner.alloc_exclusive(layout),
+            // children only exist for `Allocator`s and can't borrow the root mutably
+            Source::Child(_) => Err(AllocError),
+        };
+        if block.is_err() {
+            self.refund_until(layout.size(), None);
+        }
+        block
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        if let Source::Root(ref mut inner) = self.source {
+            unsafe { inner.dealloc_exclusive(ptr, layout) };
+        }
+        self.refund_until(layout.size(), None);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::slice_allocator::StackAllocator;
+use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::{MaybeUninit, needs_drop};
+use core::ptr;
+use core::ptr::NonNull;
+
+/// Intrusive record placed in front of every value that needs dropping.
+struct DropRecord {
+    prev: Option<NonNull<Self>>,
+    drop_fn: unsafe fn(NonNull<Self>),
+}
+
+#[repr(C)]
+struct WithDrop<T> {
+    record: DropRecord,
+    value: T,
+}
+
+unsafe fn drop_entry<T>(record: NonNull<DropRecord>) {
+    let entry = record.cast::<WithDrop<T>>().as_ptr();
+    unsafe { ptr::drop_in_place(&raw mut (*entry).value) };
+}
+
+/// A bump arena for values of any type that runs their destructors.
+///
+/// Values that need dropping get a [`DropRecord`] allocated right in front of them,
+/// and the records are walked newest-first on [`reset`](Self::reset) or when the arena
+/// drops. Values without drop glue are bumped like in
+/// [`SingleThreadedSliceAllocator`](crate::slice_allocator::SingleThreadedSliceAllocator)
+/// and cost nothing extra.
+///
+/// Values must outlive the buffer, since their destructors run whenever the arena is
+/// reset or dropped:
+///
+/// ```compile_fail
+/// # use slice_alloc::drop_arena::DropArena;
+/// let mut memory = [0u8; 64];
+/// let arena = DropArena::from_unique_slice(&mut memory);
+/// {
+///     let short_lived = 1u32;
+///     arena.alloc(std::vec![&short_lived]);
+/// }
+/// drop(arena);
+/// ```
+pub struct DropArena<'buf> {
+    alloc: UnsafeCell<StackAllocator<'buf>>,
+    last_record: Cell<Option<NonNull<DropRecord>>>,
+}
+
+impl<'buf> DropArena<'buf> {
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
+        DropArena::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_uninit_slice(slice: &'buf mut [MaybeUninit<u8>]) -> Self {
+        DropArena::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_uninit_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        DropArena::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_backing_allocation(mem))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_unaligned_generic_buffer(mem: UnalignedGenericBuffer<'buf, u8>) -> Self {
+        DropArena {
+            alloc: UnsafeCell::new(StackAllocator::from_unaligned_generic_buffer(mem)),
+            last_record: Cell::new(None),
+        }
+    }
+
+    /// Returns the amount of bytes used so far, including alignment padding and drop records.
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> usize {
+        unsafe { (*self.alloc.get()).pos() }
+    }
+
+    /// Moves `value` into the arena, handing it back if the arena is full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_alloc<T: 'buf>(&self, value: T) -> Result<&mut T, T> {
+        let allocator: &mut StackAllocator = unsafe { &mut *self.alloc.get() };
+
+        if !needs_drop::<T>() {
+            let Ok(block) = allocator.allocate(Layout::new::<T>()) else {
+                return Err(value);
+            };
+            let slot = block.cast::<T>().as_ptr();
+            unsafe { slot.write(value) };
+            return Ok(unsafe { &mut *slot });
+        }
+
+        let Ok(block) = allocator.allocate(Layout::new::<WithDrop<T>>()) else {
+            return Err(value);
+        };
+        let entry = block.cast::<WithDrop<T>>().as_ptr();
+        unsafe {
+            entry.write(WithDrop {
+                record: DropRecord {
+                    prev: self.last_record.get(),
+                    drop_fn: drop_entry::<T>,
+                },
+                value,
+            });
+        }
+        self.last_record.set(Some(block.cast::<DropRecord>()));
+
+        Ok(unsafe { &mut (*entry).value })
+    }
+
+    /// Moves `value` into the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T: 'buf>(&self, value: T) -> &mut T {
+        let Ok(slot) = self.try_alloc(value) else {
+            panic!("DropArena is full")
+        };
+        slot
+    }
+
+    /// Drops every value that needs dropping, newest first, and makes the whole arena
+    /// available again.
+    #[inline]
+    pub fn reset(&mut self) {
+        let mut record = self.last_record.take();
+        while let Some(current) = record {
+            // Safety: records are only created in `try_alloc` and stay valid until the
+            // arena is reset, which needs `&mut self` and so no value is borrowed.
+            unsafe {
+                record = current.as_ref().prev;
+                (current.as_ref().drop_fn)(current);
+            }
+        }
+
+        self.alloc.get_mut().reset();
+    }
+}
+
+impl Drop for DropArena<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
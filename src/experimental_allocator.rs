@@ -1,11 +1,87 @@
 use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
 use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
 use core::alloc::Layout;
+use core::fmt;
+use core::iter;
 use core::mem::{MaybeUninit, size_of};
 use core::ptr;
 use core::ptr::NonNull;
 #[derive(Debug, Clone, Copy)]
 pub struct ExperimentalAllocError(pub &'static str);
+/// A physical block of an [`ExperimentalAllocator`]. `size` includes the block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub addr: usize,
+    pub size: usize,
+    pub allocated: bool,
+}
+/// The state of the free space of an [`ExperimentalAllocator`]. Sizes include block headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapSummary {
+    pub free_bytes: usize,
+    pub largest_free_block: usize,
+    pub free_blocks: usize,
+    /// The lowest `free_bytes` has been since the heap was created.
+    pub min_ever_free_bytes: usize,
+}
+impl HeapSummary {
+    /// Returns the share of the free bytes outside the largest free block, from 0 to 1.
+    #[inline]
+    #[must_use]
+    pub fn fragmentation(&self) -> f64 {
+        if self.free_bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / self.free_bytes as f64
+        }
+    }
+}
+/// A heap invariant found broken by [`ExperimentalAllocator::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapCorruptionKind {
+    /// The end marker has a size, an allocated tag or a free-list link.
+    EndMarker,
+    /// The block is smaller than its header, has an unaligned size, or runs past the end
+    /// marker, so the block sizes don't add up to the heap size.
+    BlockSize,
+    /// The block is free and directly follows another free block.
+    Uncoalesced,
+    /// The block is on the free list but tagged allocated.
+    AllocatedOnFreeList,
+    /// The block is tagged free but isn't on the free list.
+    MissingFromFreeList,
+    /// The free-list link of the block is missing, or points outside the heap or inside
+    /// a block.
+    BadLink,
+    /// The free-list link of the block points back to an earlier node of the list.
+    FreeListCycle,
+    /// The free-list link of the block points to a lower address.
+    FreeListOrder,
+}
+/// The first bad block found by [`ExperimentalAllocator::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapCorruption {
+    /// Address of the block header.
+    pub block: usize,
+    pub kind: HeapCorruptionKind,
+}
+impl fmt::Display for HeapCorruption {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            HeapCorruptionKind::EndMarker => "the end marker is overwritten",
+            HeapCorruptionKind::BlockSize => "the block size is invalid",
+            HeapCorruptionKind::Uncoalesced => "the free block follows another free block",
+            HeapCorruptionKind::AllocatedOnFreeList => "the block is on the free list but tagged allocated",
+            HeapCorruptionKind::MissingFromFreeList => "the block is tagged free but not on the free list",
+            HeapCorruptionKind::BadLink => "the free-list link doesn't point to a block",
+            HeapCorruptionKind::FreeListCycle => "the free list loops back from the block",
+            HeapCorruptionKind::FreeListOrder => "the free list isn't address-ordered after the block",
+        };
+        write!(f, "heap corruption at block {:#x}: {what}", self.block)
+    }
+}
 const PORT_BYTE_ALIGNMENT: usize = 8;
 const PORT_BYTE_ALIGNMENT_MASK: usize = PORT_BYTE_ALIGNMENT - 1;
 #[repr(C)]
@@ -94,7 +170,14 @@ pub struct ExperimentalAllocator<'buf> {
     mem: UnalignedGenericBuffer<'buf, u8>,
     free_head: Option<NonNull<BlockLink>>,
     end_marker: Option<NonNull<BlockLink>>,
+    free_bytes: usize,
+    min_ever_free_bytes: usize,
+    validate_every_op: bool,
 }
+
+// Safety: the block links only point into the allocator's own buffer.
+unsafe impl Send for ExperimentalAllocator<'_> {}
+
 impl<'buf> ExperimentalAllocator<'buf> {
     #[inline]
     #[must_use]
@@ -128,6 +211,8 @@ impl<'buf> ExperimentalAllocator<'buf> {
         if end_marker_addr < heap_start.addr() || (end_marker_addr + BlockLink::HEAP_STRUCT_SIZE) > heap_end_addr {
             self.free_head = None;
             self.end_marker = None;
+            self.free_bytes = 0;
+            self.min_ever_free_bytes = 0;
             return;
         }
         let dummy_head = heap_start as *mut BlockLink;
@@ -149,11 +234,22 @@ impl<'buf> ExperimentalAllocator<'buf> {
         }
         self.free_head = unsafe { Some(NonNull::new_debug_checked(dummy_head)) };
         self.end_marker = unsafe { Some(NonNull::new_debug_checked(end_marker)) };
+        self.free_bytes = first_block_size;
+        self.min_ever_free_bytes = first_block_size;
     }
     #[inline]
     const fn align_up(addr: usize, align: usize) -> usize {
         (addr + align - 1) & !(align - 1)
     }
+    /// Like [`alloc`](Self::alloc), but fails for alignments above the block alignment,
+    /// which `alloc` doesn't honor, so the block always fits `layout`.
+    #[inline]
+    pub(crate) fn alloc_fitting(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.align() > PORT_BYTE_ALIGNMENT {
+            return Err(AllocError);
+        }
+        self.alloc(layout).map_err(|_| AllocError)
+    }
     #[inline]
     pub fn alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, ExperimentalAllocError> {
         let wanted_size = layout.size();
@@ -189,6 +285,7 @@ impl<'buf> ExperimentalAllocator<'buf> {
             unsafe {
                 (*new_block_addr).block_size = TaggedUsize::new(curr_block_size - total_size, false);
                 (*new_block_addr).next_free = curr.as_ref().next_free;
+                (*prev.as_ptr()).next_free = Some(NonNull::new_debug_checked(new_block_addr));
                 (*curr.as_ptr()).block_size = TaggedUsize::new(total_size, false);
                 (*curr.as_ptr()).next_free = None;
                 allocate_block(&mut *curr.as_ptr());
@@ -200,8 +297,11 @@ impl<'buf> ExperimentalAllocator<'buf> {
                 allocate_block(&mut *curr.as_ptr());
             }
         }
+        self.free_bytes -= unsafe { curr.as_ref().block_size.size() };
+        self.min_ever_free_bytes = self.min_ever_free_bytes.min(self.free_bytes);
         let user_ptr = unsafe { curr.as_ptr().cast::<u8>().add(BlockLink::HEAP_STRUCT_SIZE) };
         let slice = ptr::slice_from_raw_parts_mut(user_ptr, wanted_size);
+        self.validate_after_op();
         Ok(unsafe { NonNull::new_debug_checked(slice) })
     }
     #[inline]
@@ -211,7 +311,165 @@ impl<'buf> ExperimentalAllocator<'buf> {
         let next_free_ptr = unsafe { &raw mut (*block_ptr).next_free };
         unsafe { next_free_ptr.write_unaligned(None) };
         free_block(unsafe { &mut *block_ptr });
+        self.free_bytes += unsafe { (*block_ptr).block_size.size() };
         self.insert_block_into_free_list(block_ptr);
+        self.validate_after_op();
+    }
+    /// Returns every block between the start of the heap and the end marker, in address
+    /// order.
+    #[inline]
+    pub fn blocks(&self) -> impl Iterator<Item = BlockInfo> + '_ {
+        let end = self.end_marker.map_or(0, |end| end.as_ptr().addr());
+        let first = self
+            .free_head
+            .map(|head| head.as_ptr().wrapping_byte_add(BlockLink::HEAP_STRUCT_SIZE))
+            .filter(|first| first.addr() < end);
+        iter::successors(first, move |&block| {
+            let size = unsafe { (*block).block_size.size() };
+            let next = block.wrapping_byte_add(size);
+            (size != 0 && next.addr() < end).then_some(next)
+        })
+        .map(|block| {
+            let block_size = unsafe { (*block).block_size };
+            BlockInfo {
+                addr: block.addr(),
+                size: block_size.size(),
+                allocated: block_size.is_allocated(),
+            }
+        })
+    }
+    /// Returns the free bytes, kept up to date by `alloc` and `free`.
+    #[inline]
+    #[must_use]
+    pub const fn free_bytes(&self) -> usize {
+        self.free_bytes
+    }
+    /// Returns the lowest [`free_bytes`](Self::free_bytes) has been since the heap was created.
+    #[inline]
+    #[must_use]
+    pub const fn min_ever_free_bytes(&self) -> usize {
+        self.min_ever_free_bytes
+    }
+    /// Walks the heap to summarize its free space.
+    #[inline]
+    #[must_use]
+    pub fn summary(&self) -> HeapSummary {
+        self.blocks().filter(|block| !block.allocated).fold(
+            HeapSummary {
+                free_bytes: 0,
+                largest_free_block: 0,
+                free_blocks: 0,
+                min_ever_free_bytes: self.min_ever_free_bytes,
+            },
+            |summary, block| HeapSummary {
+                free_bytes: summary.free_bytes + block.size,
+                largest_free_block: summary.largest_free_block.max(block.size),
+                free_blocks: summary.free_blocks + 1,
+                ..summary
+            },
+        )
+    }
+    /// Returns the allocator with [`validate`](Self::validate) run after every `alloc` and
+    /// `free`, which then panic on corruption.
+    #[inline]
+    #[must_use]
+    pub const fn with_validation(mut self, enabled: bool) -> Self {
+        self.validate_every_op = enabled;
+        self
+    }
+    /// Walks the heap and the free list together and checks that the free list is
+    /// address-ordered and acyclic, that it holds exactly the blocks tagged free, that no
+    /// two free blocks are adjacent, that the block sizes add up to the heap size, and that
+    /// the end marker is intact.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first bad block in address order, or the end marker if it is damaged.
+    #[inline]
+    pub fn validate(&self) -> Result<(), HeapCorruption> {
+        let (Some(head), Some(end_marker)) = (self.free_head, self.end_marker) else {
+            return Ok(());
+        };
+        let corrupt = |block: usize, kind| Err(HeapCorruption { block, kind });
+        let end = end_marker.as_ptr().addr();
+        let marker = unsafe { end_marker.as_ref() };
+        if marker.block_size.0 != 0 || marker.next_free.is_some() {
+            return corrupt(end, HeapCorruptionKind::EndMarker);
+        }
+
+        let block_at = |addr: usize| head.as_ptr().wrapping_byte_add(addr - head.as_ptr().addr());
+        let mut link_from = head.as_ptr().addr();
+        let mut free_node = self.next_free_node(head, end)?;
+        let mut addr = link_from + BlockLink::HEAP_STRUCT_SIZE;
+        let mut prev_free = false;
+        while addr < end {
+            if free_node < addr {
+                return corrupt(link_from, HeapCorruptionKind::BadLink);
+            }
+            let block = unsafe { &*block_at(addr) };
+            let size = block.block_size.size();
+            if size < BlockLink::HEAP_STRUCT_SIZE || (size & PORT_BYTE_ALIGNMENT_MASK) != 0 || size > end - addr {
+                return corrupt(addr, HeapCorruptionKind::BlockSize);
+            }
+            let free = !block_is_allocated(block);
+            if free_node == addr {
+                if !free {
+                    return corrupt(addr, HeapCorruptionKind::AllocatedOnFreeList);
+                }
+                link_from = addr;
+                free_node = self.next_free_node(unsafe { NonNull::new_debug_checked(block_at(addr)) }, end)?;
+            } else if free {
+                return corrupt(addr, HeapCorruptionKind::MissingFromFreeList);
+            }
+            if free && prev_free {
+                return corrupt(addr, HeapCorruptionKind::Uncoalesced);
+            }
+            prev_free = free;
+            addr += size;
+        }
+        if free_node != end {
+            return corrupt(link_from, HeapCorruptionKind::BadLink);
+        }
+        Ok(())
+    }
+    /// Returns the address of the node following `node` on the free list, after checking
+    /// that the link stays in the heap and goes forward.
+    #[inline]
+    fn next_free_node(&self, node: NonNull<BlockLink>, end: usize) -> Result<usize, HeapCorruption> {
+        let from = node.as_ptr().addr();
+        let corrupt = |kind| Err(HeapCorruption { block: from, kind });
+        let Some(next) = (unsafe { node.as_ref().next_free }) else {
+            return corrupt(HeapCorruptionKind::BadLink);
+        };
+        let next = next.as_ptr().addr();
+        if next <= from {
+            // the nodes before `node` have been checked to go forward
+            let mut curr = unsafe { self.free_head.unwrap_debug_checked() };
+            while curr.as_ptr().addr() < from {
+                if curr.as_ptr().addr() == next {
+                    return corrupt(HeapCorruptionKind::FreeListCycle);
+                }
+                curr = unsafe { curr.as_ref().next_free.unwrap_debug_checked() };
+            }
+            let kind = if next == from {
+                HeapCorruptionKind::FreeListCycle
+            } else {
+                HeapCorruptionKind::FreeListOrder
+            };
+            return corrupt(kind);
+        }
+        if next > end || (next & PORT_BYTE_ALIGNMENT_MASK) != 0 {
+            return corrupt(HeapCorruptionKind::BadLink);
+        }
+        Ok(next)
+    }
+    #[inline]
+    fn validate_after_op(&self) {
+        if self.validate_every_op
+            && let Err(corruption) = self.validate()
+        {
+            panic!("{corruption}");
+        }
     }
     #[inline]
     fn insert_block_into_free_list(&mut self, block: *mut BlockLink) {
@@ -234,7 +492,7 @@ impl<'buf> ExperimentalAllocator<'buf> {
             }
         }
         let block_end = unsafe { (block as usize) + ((*block).block_size.size()) };
-        if block_end == curr.as_ptr() as usize {
+        if block_end == curr.as_ptr() as usize && curr != unsafe { self.end_marker.unwrap_debug_checked() } {
             unsafe {
                 (*block).block_size = TaggedUsize::new((*block).block_size.size() + curr.as_ref().block_size.size(), false);
                 (*block).next_free = curr.as_ref().next_free;
@@ -251,6 +509,9 @@ impl<'buf> ExperimentalAllocator<'buf> {
             mem: ugb,
             free_head: None,
             end_marker: None,
+            free_bytes: 0,
+            min_ever_free_bytes: 0,
+            validate_every_op: false,
         };
         alloc.init_heap();
         alloc
@@ -307,4 +568,29 @@ mod tests {
         let block4 = allocator.alloc(Layout::from_size_align(150, 1).unwrap());
         assert!(block4.is_ok(), "Block4 allocation should succeed using freed space");
     }
+    #[test]
+    fn test_split_block_leaves_free_list() {
+        let mut allocator = create_allocator(1024);
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        let block1 = allocator.alloc(layout).unwrap().cast::<u8>();
+        let block2 = allocator.alloc(layout).unwrap().cast::<u8>();
+        assert_ne!(block1, block2, "An allocated block must not be handed out again");
+    }
+    #[test]
+    fn test_free_last_block_keeps_end_marker() {
+        let mut allocator = create_allocator(1024);
+        // the largest block spans the whole heap, up to the end marker
+        let (ptr, layout) = (1..1024)
+            .rev()
+            .find_map(|size| {
+                let layout = Layout::from_size_align(size, 1).unwrap();
+                allocator.alloc(layout).ok().map(|block| (block.cast::<u8>(), layout))
+            })
+            .unwrap();
+        unsafe {
+            allocator.free(ptr, layout);
+        }
+        assert!(allocator.alloc(Layout::from_size_align(1024, 1).unwrap()).is_err(), "Allocation should fail for too big request");
+        assert!(allocator.alloc(layout).is_ok(), "Allocation after free should succeed");
+    }
 }
\ No newline at end of file
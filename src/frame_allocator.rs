@@ -0,0 +1,164 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::slice_allocator::StackAllocator;
+use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// One bump half of a [`FrameAllocator`].
+pub struct FrameHalf<'buf> {
+    alloc: UnsafeCell<StackAllocator<'buf>>,
+    high_water: Cell<usize>,
+}
+
+impl<'buf> FrameHalf<'buf> {
+    #[inline]
+    const fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        let ugb = UnalignedGenericBuffer::from_backing_allocation(mem);
+        FrameHalf {
+            alloc: UnsafeCell::new(StackAllocator::from_unaligned_generic_buffer(ugb)),
+            high_water: Cell::new(0),
+        }
+    }
+
+    /// Resets this half and starts a new frame in it.
+    ///
+    /// Everything allocated here is borrowed from the returned reference, so it can't be
+    /// used once `begin_frame` is called on this half again, two frames later.
+    #[inline]
+    pub const fn begin_frame(&mut self) -> &Self {
+        self.alloc.get_mut().reset();
+        self
+    }
+
+    #[inline]
+    pub fn alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let allocator: &mut StackAllocator = unsafe { &mut *self.alloc.get() };
+        let block = allocator.allocate(layout)?;
+
+        if allocator.pos() > self.high_water.get() {
+            self.high_water.set(allocator.pos());
+        }
+
+        Ok(block)
+    }
+
+    /// Returns the amount of bytes used in the current frame.
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> usize {
+        unsafe { (*self.alloc.get()).pos() }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        unsafe { (*self.alloc.get()).capacity() }
+    }
+
+    /// Returns the most bytes this half ever had in use during a single frame.
+    #[inline]
+    #[must_use]
+    pub const fn high_water_mark(&self) -> usize {
+        self.high_water.get()
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl Allocator for FrameHalf<'_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.alloc(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let allocator: &mut StackAllocator = unsafe { &mut *self.alloc.get() };
+        unsafe { allocator.deallocate(ptr, layout) };
+    }
+}
+
+/// A double-buffered allocator for per-frame temporaries.
+///
+/// The backing allocation is split into two [`FrameHalf`]s, and frames alternate between
+/// them. Memory allocated in frame N stays valid through frame N+1 and is reclaimed when
+/// frame N+2 begins in the same half:
+///
+/// ```compile_fail
+/// # #![feature(allocator_api)]
+/// # extern crate alloc;
+/// # use alloc::boxed::Box;
+/// # use slice_alloc::frame_allocator::FrameAllocator;
+/// # let mut memory = [0u8; 256];
+/// # let mut frames = FrameAllocator::from_unique_slice(&mut memory);
+/// let (even, odd) = frames.halves_mut();
+/// let frame0 = even.begin_frame();
+/// let a = Box::new_in(1, frame0);
+/// let frame1 = odd.begin_frame();
+/// let b = Box::new_in(*a + 1, frame1); // frame 0 data is still valid in frame 1
+/// let frame2 = even.begin_frame(); // doesn't compile while `a` is alive
+/// drop(a);
+/// ```
+pub struct FrameAllocator<'buf> {
+    even: FrameHalf<'buf>,
+    odd: FrameHalf<'buf>,
+    next_odd: bool,
+}
+
+impl<'buf> FrameAllocator<'buf> {
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
+        FrameAllocator::from_backing_allocation(BackingAllocation::from_unique_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_uninit_slice(slice: &'buf mut [MaybeUninit<u8>]) -> Self {
+        FrameAllocator::from_backing_allocation(BackingAllocation::from_unique_uninit_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        let mid = mem.len() / 2;
+        let (even, odd) = mem.split_at(mid);
+        FrameAllocator {
+            even: FrameHalf::from_backing_allocation(even),
+            odd: FrameHalf::from_backing_allocation(odd),
+            next_odd: false,
+        }
+    }
+
+    /// Switches to the other half and starts a new frame in it, starting with the even half.
+    ///
+    /// The returned half borrows the whole allocator, so data from the previous frame can't
+    /// be kept alive into this one. Use [`halves_mut`](Self::halves_mut) to hold on to it.
+    #[inline]
+    pub const fn begin_frame(&mut self) -> &FrameHalf<'buf> {
+        let half = if self.next_odd { &mut self.odd } else { &mut self.even };
+        self.next_odd = !self.next_odd;
+        half.begin_frame()
+    }
+
+    /// Returns the even and odd halves, each of which begins its own frames.
+    #[inline]
+    pub const fn halves_mut(&mut self) -> (&mut FrameHalf<'buf>, &mut FrameHalf<'buf>) {
+        (&mut self.even, &mut self.odd)
+    }
+
+    /// Returns the high-water marks of the even and odd halves.
+    #[inline]
+    #[must_use]
+    pub const fn high_water_marks(&self) -> [usize; 2] {
+        [self.even.high_water_mark(), self.odd.high_water_mark()]
+    }
+}
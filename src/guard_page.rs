@@ -0,0 +1,226 @@
+use crate::backing_alloc::BackingAllocation;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ffi::{c_int, c_long, c_void};
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+use core::slice;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// the module is only built for architectures using these values; mips, sparc and alpha,
+// among others, use different ones
+const PROT_NONE: c_int = 0;
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const SC_PAGESIZE: c_int = 30;
+
+unsafe extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn sysconf(name: c_int) -> c_long;
+}
+
+/// Which side of each block the inaccessible page of a [`GuardPageAllocator`] goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardSide {
+    /// After the block, which ends as close to the page as its alignment allows. Catches
+    /// overruns.
+    End,
+    /// Before the block, which starts on a page boundary. Catches underruns.
+    Start,
+}
+
+/// A mapping made by a [`GuardPageAllocator`], guard page included.
+#[derive(Clone, Copy)]
+struct Region {
+    base: NonNull<u8>,
+    len: usize,
+}
+
+// Safety: a region only records a mapping, which isn't tied to a thread.
+unsafe impl Send for Region {}
+
+impl Region {
+    unsafe fn protect(self, prot: c_int) -> bool {
+        unsafe { mprotect(self.base.as_ptr().cast(), self.len, prot) == 0 }
+    }
+
+    unsafe fn unmap(self) {
+        unsafe { munmap(self.base.as_ptr().cast(), self.len) };
+    }
+}
+
+/// An electric-fence style allocator for hunting memory bugs, mapping every block into
+/// its own pages next to an inaccessible one so that overruns or underruns fault at once.
+///
+/// Freed blocks are made inaccessible too, and stay mapped until `quarantine` more blocks
+/// have been freed, so that uses after free fault as well.
+///
+/// Every block takes at least one page plus the guard page. Alignments above the page
+/// size aren't supported. With [`GuardSide::End`], a block whose size isn't a multiple of
+/// its alignment ends up to `align - 1` bytes short of the guard page.
+pub struct GuardPageAllocator {
+    side: GuardSide,
+    page_size: usize,
+    quarantine_len: usize,
+    quarantine: Mutex<VecDeque<Region>>,
+    backings: Mutex<Vec<Region>>,
+}
+
+impl GuardPageAllocator {
+    /// Creates an allocator keeping the last `quarantine` freed blocks mapped but
+    /// inaccessible.
+    #[inline]
+    #[must_use]
+    pub fn new(side: GuardSide, quarantine: usize) -> Self {
+        let page_size = usize::try_from(unsafe { sysconf(SC_PAGESIZE) }).unwrap_or(4096);
+        Self {
+            side,
+            page_size,
+            quarantine_len: quarantine,
+            quarantine: Mutex::new(VecDeque::with_capacity(quarantine)),
+            backings: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn side(&self) -> GuardSide {
+        self.side
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Maps `len` bytes against a guard page on the configured side, to back another
+    /// allocator. They stay mapped until this allocator is dropped.
+    #[inline]
+    pub fn backing(&self, len: usize) -> Result<BackingAllocation<'_>, AllocError> {
+        let data_len = self.data_len(len)?;
+        let (region, data) = self.map(data_len)?;
+        self.backings.lock().unwrap_or_else(PoisonError::into_inner).push(region);
+
+        let start = match self.side {
+            GuardSide::End => unsafe { data.add(data_len - len) },
+            GuardSide::Start => data,
+        };
+        // Safety: the bytes are mapped for reads and writes, and only handed out here.
+        let slice = unsafe { slice::from_raw_parts_mut(start.as_ptr().cast::<MaybeUninit<u8>>(), len) };
+        Ok(BackingAllocation::from_unique_uninit_slice(slice))
+    }
+
+    #[inline]
+    fn quarantine(&self) -> MutexGuard<'_, VecDeque<Region>> {
+        self.quarantine.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn data_len(&self, size: usize) -> Result<usize, AllocError> {
+        size.checked_next_multiple_of(self.page_size).ok_or(AllocError)
+    }
+
+    /// Maps `data_len` accessible bytes next to an inaccessible page, returning the
+    /// mapping and the start of the accessible bytes.
+    fn map(&self, data_len: usize) -> Result<(Region, NonNull<u8>), AllocError> {
+        let len = data_len.checked_add(self.page_size).ok_or(AllocError)?;
+        let base = unsafe { mmap(ptr::null_mut(), len, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) };
+        // MAP_FAILED
+        if base.addr() == usize::MAX {
+            return Err(AllocError);
+        }
+        let base = NonNull::new(base.cast::<u8>()).ok_or(AllocError)?;
+        let region = Region { base, len };
+
+        let data = match self.side {
+            GuardSide::End => base,
+            GuardSide::Start => unsafe { base.add(self.page_size) },
+        };
+        let data_region = Region { base: data, len: data_len };
+        if data_len != 0 && !unsafe { data_region.protect(PROT_READ | PROT_WRITE) } {
+            unsafe { region.unmap() };
+            return Err(AllocError);
+        }
+        Ok((region, data))
+    }
+
+    /// Returns the mapping of a block handed out by `allocate`.
+    fn region_of(&self, ptr: NonNull<u8>, layout: Layout) -> Region {
+        let data_len = layout.size().next_multiple_of(self.page_size);
+        let base = match self.side {
+            GuardSide::End => {
+                let data_end = (ptr.addr().get() + layout.size()).next_multiple_of(self.page_size);
+                unsafe { ptr.sub(ptr.addr().get() - (data_end - data_len)) }
+            }
+            GuardSide::Start => unsafe { ptr.sub(self.page_size) },
+        };
+        Region { base, len: data_len + self.page_size }
+    }
+
+    /// Makes a freed block inaccessible and queues it, unmapping the oldest queued one
+    /// once there are more than `quarantine`. A block that can't be made inaccessible is
+    /// unmapped right away.
+    fn retire(&self, region: Region) {
+        if !unsafe { region.protect(PROT_NONE) } {
+            unsafe { region.unmap() };
+            return;
+        }
+        let evicted = {
+            let mut quarantine = self.quarantine();
+            quarantine.push_back(region);
+            if quarantine.len() > self.quarantine_len {
+                quarantine.pop_front()
+            } else {
+                None
+            }
+        };
+        if let Some(evicted) = evicted {
+            unsafe { evicted.unmap() };
+        }
+    }
+}
+
+unsafe impl Allocator for GuardPageAllocator {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.align() > self.page_size {
+            return Err(AllocError);
+        }
+        let data_len = self.data_len(layout.size())?;
+        let (_, data) = self.map(data_len)?;
+
+        let ptr = match self.side {
+            GuardSide::End => unsafe { data.add((data_len - layout.size()) & !(layout.align() - 1)) },
+            GuardSide::Start => data,
+        };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    /// Anonymous mappings are zero-filled already.
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.retire(self.region_of(ptr, layout));
+    }
+}
+
+impl Drop for GuardPageAllocator {
+    #[inline]
+    fn drop(&mut self) {
+        let quarantine = self.quarantine.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let backings = self.backings.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for region in quarantine.drain(..).chain(backings.drain(..)) {
+            unsafe { region.unmap() };
+        }
+    }
+}
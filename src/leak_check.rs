@@ -0,0 +1,280 @@
+use crate::observer::AllocObserver;
+use core::alloc::Layout;
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::panic::Location;
+use core::ptr::NonNull;
+
+#[cfg(feature = "std")]
+use std::thread;
+
+/// A live allocation recorded by a [`LeakTracker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveAlloc {
+    pub ptr: NonNull<u8>,
+    pub layout: Layout,
+    pub location: &'static Location<'static>,
+    /// Position of the allocation among all allocations seen by the tracker, from 0.
+    pub seq: u64,
+}
+
+impl fmt::Display for LiveAlloc {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} bytes (align {}) at {:p}, allocated at {}",
+            self.seq,
+            self.layout.size(),
+            self.layout.align(),
+            self.ptr,
+            self.location
+        )
+    }
+}
+
+/// A fixed-capacity table of live allocations, numbering them in allocation order.
+pub(crate) struct LiveTable<const N: usize> {
+    entries: UnsafeCell<[Option<LiveAlloc>; N]>,
+    next_seq: Cell<u64>,
+    untracked: Cell<usize>,
+}
+
+impl<const N: usize> LiveTable<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            entries: UnsafeCell::new([None; N]),
+            next_seq: Cell::new(0),
+            untracked: Cell::new(0),
+        }
+    }
+
+    pub(crate) const fn next_seq(&self) -> u64 {
+        self.next_seq.get()
+    }
+
+    pub(crate) const fn untracked(&self) -> usize {
+        self.untracked.get()
+    }
+
+    fn entries(&self) -> &[Cell<Option<LiveAlloc>>; N] {
+        // Safety: the entries are only accessed through cells, and the table isn't `Sync`.
+        unsafe { &*self.entries.get().cast::<[Cell<Option<LiveAlloc>>; N]>() }
+    }
+
+    fn find(&self, ptr: NonNull<u8>) -> Option<&Cell<Option<LiveAlloc>>> {
+        self.entries()
+            .iter()
+            .find(|entry| entry.get().is_some_and(|alloc| alloc.ptr == ptr))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = LiveAlloc> + '_ {
+        self.entries().iter().filter_map(Cell::get)
+    }
+
+    pub(crate) fn get(&self, ptr: NonNull<u8>) -> Option<LiveAlloc> {
+        self.find(ptr).and_then(Cell::get)
+    }
+
+    /// Returns the allocation whose block contains `ptr` past its first byte.
+    pub(crate) fn containing(&self, ptr: NonNull<u8>) -> Option<LiveAlloc> {
+        self.iter()
+            .find(|alloc| alloc.ptr < ptr && ptr.addr().get() - alloc.ptr.addr().get() < alloc.layout.size())
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.entries().iter().all(|entry| entry.get().is_some())
+    }
+
+    pub(crate) fn insert(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq + 1);
+
+        match self.entries().iter().find(|entry| entry.get().is_none()) {
+            Some(entry) => entry.set(Some(LiveAlloc {
+                ptr,
+                layout,
+                location,
+                seq,
+            })),
+            None => self.untracked.set(self.untracked.get() + 1),
+        }
+    }
+
+    pub(crate) fn remove(&self, ptr: NonNull<u8>) {
+        if let Some(entry) = self.find(ptr) {
+            entry.set(None);
+        }
+    }
+
+    /// Moves the entry of `old_ptr` to the new block, returning whether there was one.
+    pub(crate) fn moved(&self, old_ptr: NonNull<u8>, new_ptr: NonNull<u8>, new_layout: Layout) -> bool {
+        if let Some(entry) = self.find(old_ptr)
+            && let Some(alloc) = entry.get()
+        {
+            entry.set(Some(LiveAlloc {
+                ptr: new_ptr,
+                layout: new_layout,
+                ..alloc
+            }));
+            return true;
+        }
+        false
+    }
+}
+
+/// What a [`LeakTracker`] does when it finds leaked blocks.
+#[derive(Debug, Clone, Copy)]
+pub enum LeakAction {
+    /// Panics with a list of the leaked blocks. The panic is skipped if the thread is
+    /// already panicking and the `std` feature is enabled.
+    Panic,
+    /// Calls the function once per leaked block.
+    Report(fn(&LiveAlloc)),
+}
+
+/// An [`AllocObserver`] keeping the live allocations of an allocator in a side table of
+/// `N` entries, and checking for leaks when it is dropped or when a [`LeakScope`] ends.
+///
+/// Use it with [`Observed`](crate::observer::Observed), which drops the tracker right
+/// after the allocator. Allocations made while the table is full aren't tracked, see
+/// [`untracked`](Self::untracked).
+pub struct LeakTracker<const N: usize> {
+    table: LiveTable<N>,
+    action: LeakAction,
+}
+
+impl<const N: usize> LeakTracker<N> {
+    #[inline]
+    #[must_use]
+    pub const fn new(action: LeakAction) -> Self {
+        Self {
+            table: LiveTable::new(),
+            action,
+        }
+    }
+
+    /// Returns the number of allocations that couldn't be tracked because the table was
+    /// full.
+    #[inline]
+    #[must_use]
+    pub const fn untracked(&self) -> usize {
+        self.table.untracked()
+    }
+
+    /// Returns the tracked live allocations made at or after `seq`, in no particular order.
+    #[inline]
+    pub fn live_since(&self, seq: u64) -> impl Iterator<Item = LiveAlloc> + '_ {
+        self.table.iter().filter(move |alloc| alloc.seq >= seq)
+    }
+
+    /// Returns the tracked live allocations, in no particular order.
+    #[inline]
+    pub fn live(&self) -> impl Iterator<Item = LiveAlloc> + '_ {
+        self.live_since(0)
+    }
+
+    /// Applies the [`LeakAction`] to every tracked live allocation and returns how many
+    /// there are.
+    #[inline]
+    pub fn check(&self) -> usize {
+        self.check_since(0)
+    }
+
+    /// Starts a scope whose allocations must all be freed before it is dropped.
+    #[inline]
+    #[must_use]
+    pub const fn scope(&self) -> LeakScope<'_, N> {
+        LeakScope {
+            tracker: self,
+            first_seq: self.table.next_seq(),
+        }
+    }
+
+    fn check_since(&self, seq: u64) -> usize {
+        let leaks = self.live_since(seq).count();
+        if leaks == 0 {
+            return 0;
+        }
+
+        match self.action {
+            LeakAction::Panic => {
+                #[cfg(feature = "std")]
+                if thread::panicking() {
+                    return leaks;
+                }
+                panic!("{leaks} leaked allocations:{}", LeakList { tracker: self, seq });
+            }
+            LeakAction::Report(report) => self.live_since(seq).for_each(|alloc| report(&alloc)),
+        }
+        leaks
+    }
+}
+
+impl<const N: usize> AllocObserver for LeakTracker<N> {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        self.table.insert(ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, _layout: Layout, _location: &'static Location<'static>) {
+        self.table.remove(ptr);
+    }
+
+    /// Keeps the sequence number and location of the original allocation.
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        _old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        _location: &'static Location<'static>,
+    ) {
+        self.table.moved(old_ptr, new_ptr, new_layout);
+    }
+}
+
+impl<const N: usize> Drop for LeakTracker<N> {
+    #[inline]
+    fn drop(&mut self) {
+        self.check();
+    }
+}
+
+/// Checks for leaks among the allocations made during its lifetime when dropped, see
+/// [`LeakTracker::scope`].
+pub struct LeakScope<'a, const N: usize> {
+    tracker: &'a LeakTracker<N>,
+    first_seq: u64,
+}
+
+impl<const N: usize> LeakScope<'_, N> {
+    /// Returns the tracked live allocations made in this scope.
+    #[inline]
+    pub fn live(&self) -> impl Iterator<Item = LiveAlloc> + '_ {
+        self.tracker.live_since(self.first_seq)
+    }
+}
+
+impl<const N: usize> Drop for LeakScope<'_, N> {
+    #[inline]
+    fn drop(&mut self) {
+        self.tracker.check_since(self.first_seq);
+    }
+}
+
+struct LeakList<'a, const N: usize> {
+    tracker: &'a LeakTracker<N>,
+    seq: u64,
+}
+
+impl<const N: usize> fmt::Display for LeakList<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for alloc in self.tracker.live_since(self.seq) {
+            write!(f, "\n  {alloc}")?;
+        }
+        Ok(())
+    }
+}
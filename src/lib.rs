@@ -10,16 +10,57 @@
 
 // used for slice_allocator
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 pub mod aligned_generic_buffer;
 pub mod aligned_raw_slice;
+pub mod async_allocator;
 pub mod backing_alloc;
+#[cfg(feature = "allocator_api")]
+pub mod budgeted;
 pub mod const_allocator_shared;
 pub mod const_vec;
+pub mod drop_arena;
 pub mod experimental_allocator;
+pub mod frame_allocator;
+#[cfg(all(
+    feature = "std",
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+))]
+pub mod guard_page;
+pub mod leak_check;
+#[cfg(target_has_atomic = "64")]
+pub mod lock_free_pool;
+pub mod locked_allocator;
+pub mod observer;
+pub mod poison;
+#[cfg(feature = "std")]
+pub mod profile;
 #[cfg(all(feature = "real_const_alloc", feature = "allocator_api"))]
 pub mod real_const_allocator;
+pub mod reclaim;
+pub mod red_zone;
+pub mod scratch;
+pub mod shadow;
 pub mod slice_allocator;
+pub mod stats;
+pub mod sub_arena;
+#[cfg(feature = "allocator_api")]
+pub mod tagged;
+#[cfg(feature = "std")]
+pub mod thread_arena;
+#[cfg(feature = "std")]
+pub mod thread_cache;
+pub mod trace;
+pub mod typed_arena;
 pub mod unaligned_const_allocator;
 pub mod unaligned_generic_buffer;
 pub mod weird_allocator;
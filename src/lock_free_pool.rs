@@ -0,0 +1,217 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::const_allocator_shared::next_aligned_addr;
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// Smallest block alignment, so a free block can hold its link as an `AtomicU32`.
+const MIN_BLOCK_ALIGN: usize = 4;
+
+/// A `Sync` pool of equally sized blocks in a caller-provided buffer.
+///
+/// Free blocks form an intrusive Treiber stack of block indices. The stack head packs a
+/// generation counter next to the top index, and every successful push or pop bumps it,
+/// so a pop can't succeed against a head that was popped and pushed back in between
+/// (the ABA problem). Blocks that were never handed out are taken from a bump index
+/// first, so creating a pool doesn't touch the buffer.
+pub struct LockFreePool<'buf> {
+    mem: *mut u8,
+    len: usize,
+    block_size: usize,
+    block_align: usize,
+    /// Generation in the high half, `index + 1` of the top free block in the low half.
+    head: AtomicU64,
+    next_unused: AtomicU32,
+    _marker: PhantomData<&'buf mut [u8]>,
+}
+
+// Safety: the buffer is only handed out block by block, through atomic operations.
+unsafe impl Send for LockFreePool<'_> {}
+unsafe impl Sync for LockFreePool<'_> {}
+
+impl<'buf> LockFreePool<'buf> {
+    /// Creates a pool of blocks fitting `block` in `mem`.
+    #[inline]
+    #[must_use]
+    pub const fn from_backing_allocation(mut mem: BackingAllocation<'buf>, block: Layout) -> Self {
+        // Safety: the backing allocation is valid for `'buf`.
+        unsafe { LockFreePool::from_raw_parts(mem.as_mut_ptr(), mem.len(), block) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_slice(slice: &'buf mut [u8], block: Layout) -> Self {
+        LockFreePool::from_backing_allocation(BackingAllocation::from_unique_slice(slice), block)
+    }
+
+    /// Creates a pool over raw memory, for example a `static mut` buffer in a `static`
+    /// initializer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes of `len` bytes for `'buf`, and must not
+    /// be accessed other than through the pool.
+    #[inline]
+    #[must_use]
+    pub const unsafe fn from_raw_parts(ptr: *mut u8, len: usize, block: Layout) -> Self {
+        let block_align = if block.align() > MIN_BLOCK_ALIGN {
+            block.align()
+        } else {
+            MIN_BLOCK_ALIGN
+        };
+        let block_size = if block.size() > block_align {
+            block.size().next_multiple_of(block_align)
+        } else {
+            block_align
+        };
+
+        LockFreePool {
+            mem: ptr,
+            len,
+            block_size,
+            block_align,
+            head: AtomicU64::new(0),
+            next_unused: AtomicU32::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the layout of every block, which fits the layout the pool was created for.
+    #[inline]
+    #[must_use]
+    pub const fn block_layout(&self) -> Layout {
+        // Safety: block_align is a power of two and block_size is a multiple of it.
+        unsafe { Layout::from_size_align_unchecked(self.block_size, self.block_align) }
+    }
+
+    #[inline]
+    fn base(&self) -> *mut u8 {
+        self.mem.map_addr(|addr| next_aligned_addr(addr, self.block_align))
+    }
+
+    /// Returns the amount of blocks in the pool.
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        let padding = self.base().addr() - self.mem.addr();
+        let blocks = self.len.saturating_sub(padding) / self.block_size;
+        blocks.min(u32::MAX as usize)
+    }
+
+    #[inline]
+    fn block(&self, index: u32) -> *mut u8 {
+        unsafe { self.base().add(index as usize * self.block_size) }
+    }
+
+    #[inline]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn link(&self, index: u32) -> &AtomicU32 {
+        // Safety: blocks are at least 4 bytes and aligned to at least 4.
+        unsafe { AtomicU32::from_ptr(self.block(index).cast::<u32>()) }
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<u32> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let top = head as u32;
+            if top == 0 {
+                return None;
+            }
+
+            // the link may be stale if another thread wins the race,
+            // in which case the generation has moved on and the exchange fails
+            let next = self.link(top - 1).load(Ordering::Relaxed);
+            let new_head = ((head >> 32).wrapping_add(1) << 32) | next as u64;
+            match self
+                .head
+                .compare_exchange_weak(head, new_head, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(top - 1),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    #[inline]
+    fn push(&self, index: u32) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            self.link(index).store(head as u32, Ordering::Relaxed);
+            let new_head = ((head >> 32).wrapping_add(1) << 32) | (index as u64 + 1);
+            match self
+                .head
+                .compare_exchange_weak(head, new_head, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    #[inline]
+    fn take_unused(&self) -> Option<u32> {
+        let capacity = self.capacity() as u32;
+        self.next_unused
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| (next < capacity).then_some(next + 1))
+            .ok()
+    }
+
+    /// Hands out a block if `layout` fits the pool's block layout.
+    #[inline]
+    pub fn alloc_block(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() > self.block_size || layout.align() > self.block_align {
+            return Err(AllocError);
+        }
+
+        let index = self.pop().or_else(|| self.take_unused()).ok_or(AllocError)?;
+        let block = ptr::slice_from_raw_parts_mut(self.block(index), self.block_size);
+        NonNull::new(block).ok_or(AllocError)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be a block handed out by this pool that hasn't been freed yet.
+    #[inline]
+    pub unsafe fn free_block(&self, ptr: NonNull<u8>) {
+        let offset = unsafe { ptr.as_ptr().offset_from_unsigned(self.base()) };
+        self.push((offset / self.block_size) as u32);
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl Allocator for LockFreePool<'_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.alloc_block(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        unsafe { self.free_block(ptr) };
+    }
+}
+
+unsafe impl GlobalAlloc for LockFreePool<'_> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_block(layout)
+            .map_or(ptr::null_mut(), |block| block.cast::<u8>().as_ptr())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { self.free_block(ptr) };
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::const_allocator_shared::AllocError;
+use crate::experimental_allocator::ExperimentalAllocator;
+use crate::slice_allocator::StackAllocator;
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::hint;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "allocator_api")]
+use crate::slice_allocator::SingleThreadedSliceAllocator;
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+#[cfg(feature = "std")]
+use std::sync::{Condvar, Mutex, PoisonError};
+
+/// A lock without a guarded value, in the style of `lock_api`.
+///
+/// # Safety
+///
+/// Between a return from [`lock`](Self::lock) and the matching call to
+/// [`unlock`](Self::unlock), no other call to `lock` may return.
+pub unsafe trait RawLock {
+    fn lock(&self);
+
+    /// # Safety
+    ///
+    /// The lock must be held by the current context.
+    unsafe fn unlock(&self);
+}
+
+/// A test-and-test-and-set spin lock.
+#[derive(Debug, Default)]
+pub struct SpinLock {
+    locked: AtomicBool,
+}
+
+impl SpinLock {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+        }
+    }
+}
+
+unsafe impl RawLock for SpinLock {
+    #[inline]
+    fn lock(&self) {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A platform critical section, for example masking interrupts on a single-core target.
+///
+/// # Safety
+///
+/// Code between [`acquire`](Self::acquire) and the matching [`release`](Self::release)
+/// must not run concurrently with any other such section.
+pub unsafe trait CriticalSection {
+    /// What is needed to restore the previous state, such as the interrupt mask.
+    type State: Copy;
+
+    fn acquire() -> Self::State;
+
+    /// # Safety
+    ///
+    /// `state` must come from the matching [`acquire`](Self::acquire), and sections must
+    /// be released in reverse order of acquisition.
+    unsafe fn release(state: Self::State);
+}
+
+/// A [`RawLock`] entering the critical section `C` while held.
+///
+/// Critical sections usually nest, so locking it again from inside the section, for
+/// example from an interrupt handler, would succeed. It panics instead.
+pub struct CriticalSectionLock<C: CriticalSection> {
+    /// The state to restore on unlock, `Some` while the lock is held.
+    state: UnsafeCell<Option<C::State>>,
+}
+
+// Safety: the state is only accessed inside the critical section.
+unsafe impl<C: CriticalSection> Sync for CriticalSectionLock<C> {}
+
+impl<C: CriticalSection> CriticalSectionLock<C> {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: UnsafeCell::new(None),
+        }
+    }
+}
+
+impl<C: CriticalSection> Default for CriticalSectionLock<C> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<C: CriticalSection> RawLock for CriticalSectionLock<C> {
+    #[inline]
+    fn lock(&self) {
+        let state = C::acquire();
+        // Safety: inside the critical section nothing else accesses the state.
+        let held = unsafe { &mut *self.state.get() };
+        if held.is_some() {
+            unsafe { C::release(state) };
+            panic!("CriticalSectionLock locked again while held");
+        }
+        *held = Some(state);
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        if let Some(state) = unsafe { (*self.state.get()).take() } {
+            unsafe { C::release(state) };
+        }
+    }
+}
+
+/// A [`RawLock`] that parks waiting threads on a `std` mutex and condition variable.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct StdMutexLock {
+    locked: Mutex<bool>,
+    unlocked: Condvar,
+}
+
+#[cfg(feature = "std")]
+impl StdMutexLock {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            locked: Mutex::new(false),
+            unlocked: Condvar::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl RawLock for StdMutexLock {
+    #[inline]
+    fn lock(&self) {
+        // the flag stays consistent even if a holder panicked
+        let locked = self.locked.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut locked = self
+            .unlocked
+            .wait_while(locked, |locked| *locked)
+            .unwrap_or_else(PoisonError::into_inner);
+        *locked = true;
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {
+        *self.locked.lock().unwrap_or_else(PoisonError::into_inner) = false;
+        self.unlocked.notify_one();
+    }
+}
+
+/// An allocator that needs exclusive access to allocate and free, and can be shared
+/// behind a [`LockedAllocator`].
+///
+/// # Safety
+///
+/// Blocks returned by [`alloc_exclusive`](Self::alloc_exclusive) must fit the requested
+/// layout and stay valid until passed to [`dealloc_exclusive`](Self::dealloc_exclusive).
+pub unsafe trait ExclusiveAllocator {
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`alloc_exclusive`](Self::alloc_exclusive) on this
+    /// allocator with the same `layout`, and must not be used afterwards.
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout);
+}
+
+unsafe impl ExclusiveAllocator for StackAllocator<'_> {
+    #[inline]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.deallocate(ptr, layout) };
+    }
+}
+
+unsafe impl ExclusiveAllocator for ExperimentalAllocator<'_> {
+    #[inline]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc_fitting(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.free(ptr, layout) };
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl ExclusiveAllocator for SingleThreadedSliceAllocator<'_> {
+    #[inline]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate(layout).map_err(|_| AllocError)
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.deallocate(ptr, layout) };
+    }
+}
+
+/// Makes an [`ExclusiveAllocator`] shareable by taking the lock `L` around every call.
+///
+/// The same heap code can run under a [`SpinLock`] or [`CriticalSectionLock`] in
+/// firmware and under a `StdMutexLock` in host tests.
+pub struct LockedAllocator<L: RawLock, A> {
+    lock: L,
+    inner: UnsafeCell<A>,
+}
+
+// Safety: the inner allocator is only accessed while the lock is held.
+unsafe impl<L: RawLock + Sync, A: Send> Sync for LockedAllocator<L, A> {}
+
+struct Unlock<'a, L: RawLock>(&'a L);
+
+impl<L: RawLock> Drop for Unlock<'_, L> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { self.0.unlock() };
+    }
+}
+
+impl<L: RawLock, A> LockedAllocator<L, A> {
+    #[inline]
+    #[must_use]
+    pub const fn new(lock: L, inner: A) -> Self {
+        Self {
+            lock,
+            inner: UnsafeCell::new(inner),
+        }
+    }
+
+    /// Runs `f` with the inner allocator while holding the lock.
+    ///
+    /// The lock is released even if `f` panics. Calling back into this allocator from `f`
+    /// deadlocks or panics, depending on the lock.
+    #[inline]
+    pub fn with_locked<R, F: FnOnce(&mut A) -> R>(&self, f: F) -> R {
+        self.lock.lock();
+        let _unlock = Unlock(&self.lock);
+        // Safety: holding the lock makes this the only reference to the inner allocator.
+        f(unsafe { &mut *self.inner.get() })
+    }
+
+    #[inline]
+    pub const fn get_mut(&mut self) -> &mut A {
+        self.inner.get_mut()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.inner.into_inner()
+    }
+}
+
+impl<L: RawLock, A: ExclusiveAllocator> LockedAllocator<L, A> {
+    #[inline]
+    fn locked_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with_locked(|inner| inner.alloc_exclusive(layout))
+    }
+
+    #[inline]
+    unsafe fn locked_dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with_locked(|inner| unsafe { inner.dealloc_exclusive(ptr, layout) });
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<L: RawLock, A: ExclusiveAllocator> Allocator for LockedAllocator<L, A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.locked_alloc(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.locked_dealloc(ptr, layout) };
+    }
+}
+
+unsafe impl<L: RawLock, A: ExclusiveAllocator> GlobalAlloc for LockedAllocator<L, A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.locked_alloc(layout)
+            .map_or(ptr::null_mut(), |block| block.cast::<u8>().as_ptr())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { self.locked_dealloc(ptr, layout) };
+        }
+    }
+}
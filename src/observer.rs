@@ -0,0 +1,233 @@
+use crate::const_allocator_shared::AllocError;
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::Layout;
+use core::panic::Location;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// Callbacks for everything going through an [`Observed`] allocator.
+///
+/// `location` is the caller of the allocator method. Allocations made by collections
+/// usually report a location inside the collection.
+pub trait AllocObserver {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        let _ = (ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        let _ = (ptr, layout, location);
+    }
+
+    /// Called after a successful grow or shrink. The block at `old_ptr` is no longer valid.
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        location: &'static Location<'static>,
+    ) {
+        let _ = (old_ptr, old_layout, new_ptr, new_layout, location);
+    }
+
+    /// Called when allocating, growing or shrinking to `layout` fails.
+    #[inline]
+    fn on_failure(&self, layout: Layout, location: &'static Location<'static>) {
+        let _ = (layout, location);
+    }
+}
+
+impl<O: AllocObserver + ?Sized> AllocObserver for &O {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        (**self).on_alloc(ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        (**self).on_dealloc(ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        location: &'static Location<'static>,
+    ) {
+        (**self).on_realloc(old_ptr, old_layout, new_ptr, new_layout, location);
+    }
+
+    #[inline]
+    fn on_failure(&self, layout: Layout, location: &'static Location<'static>) {
+        (**self).on_failure(layout, location);
+    }
+}
+
+/// Observes nothing, for example to fill a slot of an observer pair.
+impl AllocObserver for () {}
+
+/// Notifies both observers, first then second.
+impl<O1: AllocObserver, O2: AllocObserver> AllocObserver for (O1, O2) {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        self.0.on_alloc(ptr, layout, location);
+        self.1.on_alloc(ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        self.0.on_dealloc(ptr, layout, location);
+        self.1.on_dealloc(ptr, layout, location);
+    }
+
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        location: &'static Location<'static>,
+    ) {
+        self.0.on_realloc(old_ptr, old_layout, new_ptr, new_layout, location);
+        self.1.on_realloc(old_ptr, old_layout, new_ptr, new_layout, location);
+    }
+
+    #[inline]
+    fn on_failure(&self, layout: Layout, location: &'static Location<'static>) {
+        self.0.on_failure(layout, location);
+        self.1.on_failure(layout, location);
+    }
+}
+
+/// Wraps an allocator and reports every call to the observer `O`.
+///
+/// It forwards whichever of [`Allocator`](core::alloc::Allocator) and
+/// [`ExclusiveAllocator`] `A` implements.
+pub struct Observed<A, O> {
+    inner: A,
+    observer: O,
+}
+
+impl<A, O: AllocObserver> Observed<A, O> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A, observer: O) -> Self {
+        Self { inner, observer }
+    }
+
+    #[inline]
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[inline]
+    pub const fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    #[inline]
+    pub fn into_parts(self) -> (A, O) {
+        (self.inner, self.observer)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn observe_alloc(&self, result: Result<NonNull<[u8]>, AllocError>, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match result {
+            Ok(block) => self.observer.on_alloc(block.cast(), layout, Location::caller()),
+            Err(_) => self.observer.on_failure(layout, Location::caller()),
+        }
+        result
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[inline]
+    #[track_caller]
+    fn observe_realloc(
+        &self,
+        result: Result<NonNull<[u8]>, StdAllocError>,
+        old_ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, StdAllocError> {
+        match result {
+            Ok(block) => self
+                .observer
+                .on_realloc(old_ptr, old_layout, block.cast(), new_layout, Location::caller()),
+            Err(_) => self.observer.on_failure(new_layout, Location::caller()),
+        }
+        result
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: Allocator, O: AllocObserver> Allocator for Observed<A, O> {
+    #[inline]
+    #[track_caller]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = self.inner.allocate(layout).map_err(|_| AllocError);
+        self.observe_alloc(result, layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = self.inner.allocate_zeroed(layout).map_err(|_| AllocError);
+        self.observe_alloc(result, layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.observer.on_dealloc(ptr, layout, Location::caller());
+        unsafe { self.inner.deallocate(ptr, layout) };
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.grow(ptr, old_layout, new_layout) };
+        self.observe_realloc(result, ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.grow_zeroed(ptr, old_layout, new_layout) };
+        self.observe_realloc(result, ptr, old_layout, new_layout)
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.shrink(ptr, old_layout, new_layout) };
+        self.observe_realloc(result, ptr, old_layout, new_layout)
+    }
+}
+
+unsafe impl<A: ExclusiveAllocator, O: AllocObserver> ExclusiveAllocator for Observed<A, O> {
+    #[inline]
+    #[track_caller]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.inner.alloc_exclusive(layout);
+        self.observe_alloc(result, layout)
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.observer.on_dealloc(ptr, layout, Location::caller());
+        unsafe { self.inner.dealloc_exclusive(ptr, layout) };
+    }
+}
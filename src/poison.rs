@@ -0,0 +1,99 @@
+use core::ptr;
+
+/// The byte [`PoisonPolicy::FILL_ON_ALLOC`] writes over new blocks.
+pub const ALLOC_POISON: u8 = 0xCD;
+
+/// The byte [`PoisonPolicy::FILL_ON_FREE`] writes over freed blocks and fresh memory.
+pub const FREE_POISON: u8 = 0xAA;
+
+/// How an allocator fills the bytes it hands out and takes back, to make reads of
+/// uninitialized or freed memory stand out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoisonPolicy {
+    /// Written over blocks before they are handed out.
+    pub alloc_byte: Option<u8>,
+    /// Written over blocks when they are freed or rewound, and over fresh backing memory
+    /// before it is first handed out.
+    pub free_byte: Option<u8>,
+    /// Checks that previously freed bytes still hold `free_byte` before handing them out
+    /// again, and panics if they don't. Catches writes through dangling pointers.
+    pub verify_on_reuse: bool,
+}
+
+impl PoisonPolicy {
+    pub const NONE: Self = Self { alloc_byte: None, free_byte: None, verify_on_reuse: false };
+
+    pub const FILL_ON_FREE: Self = Self { free_byte: Some(FREE_POISON), ..Self::NONE };
+
+    pub const FILL_ON_ALLOC: Self = Self { alloc_byte: Some(ALLOC_POISON), ..Self::NONE };
+
+    pub const FILL_BOTH: Self = Self {
+        alloc_byte: Some(ALLOC_POISON),
+        free_byte: Some(FREE_POISON),
+        verify_on_reuse: false,
+    };
+
+    /// [`FILL_ON_FREE`](Self::FILL_ON_FREE) in debug builds and [`NONE`](Self::NONE)
+    /// otherwise.
+    pub const DEFAULT: Self = if cfg!(debug_assertions) { Self::FILL_ON_FREE } else { Self::NONE };
+
+    /// Returns the policy with verification on reuse turned on. It only has an effect
+    /// with a `free_byte`.
+    #[inline]
+    #[must_use]
+    pub const fn verifying(self) -> Self {
+        Self { verify_on_reuse: true, ..self }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `len` bytes.
+    #[inline]
+    pub(crate) const unsafe fn on_alloc(&self, ptr: *mut u8, len: usize) {
+        if let Some(byte) = self.alloc_byte {
+            unsafe { ptr::write_bytes(ptr, byte, len) };
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `len` bytes.
+    #[inline]
+    pub(crate) const unsafe fn on_free(&self, ptr: *mut u8, len: usize) {
+        if let Some(byte) = self.free_byte {
+            unsafe { ptr::write_bytes(ptr, byte, len) };
+        }
+    }
+
+    /// Returns the offset of the first of `len` freed bytes at `ptr` that doesn't hold the
+    /// free byte anymore, if verification is on.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` initialized bytes.
+    #[inline]
+    pub(crate) const unsafe fn find_overwritten(&self, ptr: *const u8, len: usize) -> Option<usize> {
+        let Some(byte) = self.free_byte else {
+            return None;
+        };
+        if !self.verify_on_reuse {
+            return None;
+        }
+
+        let mut i = 0;
+        while i < len {
+            if unsafe { ptr.add(i).read() } != byte {
+                return Some(i);
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+impl Default for PoisonPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
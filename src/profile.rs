@@ -0,0 +1,249 @@
+use crate::observer::AllocObserver;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::cmp::Reverse;
+use core::panic::Location;
+use core::ptr::NonNull;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Live bytes allocated from one call site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiteUsage {
+    pub location: &'static Location<'static>,
+    pub bytes: usize,
+}
+
+/// The heap at one point of a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    /// Allocator calls made before the snapshot was taken.
+    pub time: usize,
+    pub total_bytes: usize,
+    /// Call sites with live bytes, largest first.
+    pub sites: Vec<SiteUsage>,
+}
+
+#[derive(Default)]
+struct ProfileState {
+    calls: usize,
+    total_bytes: usize,
+    live: HashMap<usize, (usize, &'static Location<'static>)>,
+    sites: HashMap<&'static Location<'static>, usize>,
+    snapshots: Vec<HeapSnapshot>,
+}
+
+impl ProfileState {
+    fn add(&mut self, ptr: NonNull<u8>, size: usize, location: &'static Location<'static>) {
+        self.live.insert(ptr.addr().get(), (size, location));
+        *self.sites.entry(location).or_default() += size;
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, ptr: NonNull<u8>) {
+        let Some((size, location)) = self.live.remove(&ptr.addr().get()) else {
+            return;
+        };
+        self.total_bytes -= size;
+        if let Some(bytes) = self.sites.get_mut(location) {
+            *bytes -= size;
+            if *bytes == 0 {
+                self.sites.remove(location);
+            }
+        }
+    }
+
+    fn tick(&mut self, period: usize) {
+        self.calls += 1;
+        if period != 0 && self.calls.is_multiple_of(period) {
+            self.snapshot();
+        }
+    }
+
+    fn snapshot(&mut self) {
+        let mut sites: Vec<_> = self.sites.iter().map(|(&location, &bytes)| SiteUsage { location, bytes }).collect();
+        sites.sort_unstable_by_key(|site| {
+            (
+                Reverse(site.bytes),
+                site.location.file(),
+                site.location.line(),
+                site.location.column(),
+            )
+        });
+        self.snapshots.push(HeapSnapshot {
+            time: self.calls,
+            total_bytes: self.total_bytes,
+            sites,
+        });
+    }
+}
+
+/// An [`AllocObserver`] building a heap profile by call site, to be exported in Valgrind
+/// massif's format or as JSON.
+///
+/// A snapshot is taken every `period` allocator calls and whenever
+/// [`take_snapshot`](Self::take_snapshot) is called. Time in the exported profiles counts
+/// allocator calls.
+pub struct HeapProfiler {
+    period: usize,
+    state: Mutex<ProfileState>,
+}
+
+impl HeapProfiler {
+    /// Creates a profiler taking a snapshot every `period` calls, or only on request if
+    /// `period` is 0.
+    #[inline]
+    #[must_use]
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            state: Mutex::new(ProfileState::default()),
+        }
+    }
+
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, ProfileState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies one allocator call to the profile.
+    #[inline]
+    fn with_state<F: FnOnce(&mut ProfileState)>(&self, f: F) {
+        let mut state = self.state();
+        f(&mut state);
+        state.tick(self.period);
+    }
+
+    #[inline]
+    pub fn take_snapshot(&self) {
+        self.state().snapshot();
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn snapshots(&self) -> Vec<HeapSnapshot> {
+        self.state().snapshots.clone()
+    }
+
+    /// Writes the snapshots as a massif output file, readable by `ms_print` and
+    /// massif-visualizer. `cmd` is shown as the profiled command.
+    ///
+    /// Every snapshot is detailed, and the largest one is marked as the peak. Times count
+    /// allocator calls rather than instructions, so the time unit is given as `calls`;
+    /// `ms_print` shows it as is.
+    #[inline]
+    pub fn write_massif<W: Write>(&self, out: W, cmd: &str) -> io::Result<()> {
+        write_massif(out, cmd, &self.state().snapshots)
+    }
+
+    /// Writes the snapshots as a JSON object of the form
+    /// `{"time_unit": "calls", "snapshots": [{"time", "total_bytes", "sites": [{"file", "line", "column", "bytes"}]}]}`.
+    #[inline]
+    pub fn write_json<W: Write>(&self, out: W) -> io::Result<()> {
+        write_json(out, &self.state().snapshots)
+    }
+}
+
+fn write_massif<W: Write>(mut out: W, cmd: &str, snapshots: &[HeapSnapshot]) -> io::Result<()> {
+    let peak = snapshots
+        .iter()
+        .enumerate()
+        .max_by_key(|&(i, snapshot)| (snapshot.total_bytes, usize::MAX - i))
+        .map(|(i, _)| i);
+
+    writeln!(out, "desc: (none)")?;
+    writeln!(out, "cmd: {cmd}")?;
+    writeln!(out, "time_unit: calls")?;
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        writeln!(out, "#-----------")?;
+        writeln!(out, "snapshot={i}")?;
+        writeln!(out, "#-----------")?;
+        writeln!(out, "time={}", snapshot.time)?;
+        writeln!(out, "mem_heap_B={}", snapshot.total_bytes)?;
+        writeln!(out, "mem_heap_extra_B=0")?;
+        writeln!(out, "mem_stacks_B=0")?;
+        writeln!(out, "heap_tree={}", if peak == Some(i) { "peak" } else { "detailed" })?;
+        writeln!(
+            out,
+            "n{}: {} (heap allocation functions) malloc/new/new[], --alloc-fns, etc.",
+            snapshot.sites.len(),
+            snapshot.total_bytes
+        )?;
+        for site in &snapshot.sites {
+            writeln!(out, " n0: {} 0x0: ??? ({})", site.bytes, site.location)?;
+        }
+    }
+    out.flush()
+}
+
+fn write_json<W: Write>(mut out: W, snapshots: &[HeapSnapshot]) -> io::Result<()> {
+    write!(out, r#"{{"time_unit":"calls","snapshots":["#)?;
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        if i != 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            r#"{{"time":{},"total_bytes":{},"sites":["#,
+            snapshot.time, snapshot.total_bytes
+        )?;
+        for (j, site) in snapshot.sites.iter().enumerate() {
+            if j != 0 {
+                write!(out, ",")?;
+            }
+            write!(out, r#"{{"file":""#)?;
+            write_json_str(&mut out, site.location.file())?;
+            write!(
+                out,
+                r#"","line":{},"column":{},"bytes":{}}}"#,
+                site.location.line(),
+                site.location.column(),
+                site.bytes
+            )?;
+        }
+        write!(out, "]}}")?;
+    }
+    writeln!(out, "]}}")?;
+    out.flush()
+}
+
+fn write_json_str<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    Ok(())
+}
+
+impl AllocObserver for HeapProfiler {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+        self.with_state(|state| state.add(ptr, layout.size(), location));
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, _layout: Layout, _location: &'static Location<'static>) {
+        self.with_state(|state| state.remove(ptr));
+    }
+
+    /// Moves the block to the call site of the reallocation.
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        _old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        location: &'static Location<'static>,
+    ) {
+        self.with_state(|state| {
+            state.remove(old_ptr);
+            state.add(new_ptr, new_layout.size(), location);
+        });
+    }
+}
@@ -0,0 +1,251 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::locked_allocator::ExclusiveAllocator;
+use crate::slice_allocator::StackAllocator;
+use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// How many reclaim hooks a [`Reclaiming`] allocator can hold.
+pub const MAX_RECLAIM_HOOKS: usize = 8;
+
+/// Something that can give memory back when an allocation fails, like a cache.
+pub trait ReclaimHook {
+    /// Tries to free at least `needed` bytes, returning roughly how many were freed.
+    ///
+    /// The hook may free memory through the allocator that called it. Allocations made
+    /// from inside a hook don't run hooks again.
+    fn reclaim(&self, needed: usize) -> usize;
+}
+
+impl<F: Fn(usize) -> usize> ReclaimHook for F {
+    #[inline]
+    fn reclaim(&self, needed: usize) -> usize {
+        self(needed)
+    }
+}
+
+/// Whether an allocation may dip into the emergency reserve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Normal,
+    /// For paths that must not fail, such as error reporting.
+    High,
+}
+
+/// The emergency reserve, bump-allocating from a block of the inner allocator.
+struct Reserve<'r> {
+    alloc: StackAllocator<'r>,
+    block: NonNull<u8>,
+    layout: Layout,
+    live: usize,
+}
+
+/// Wraps an [`ExclusiveAllocator`], running reclaim hooks and retrying when it runs out
+/// of memory.
+///
+/// An emergency reserve is taken from the inner allocator up front and only serves
+/// [`Priority::High`] allocations once the inner allocator and all hooks have failed.
+/// Zero-size allocations are never served from the reserve.
+///
+/// The wrapper is single-threaded and isn't `Sync`.
+pub struct Reclaiming<'a, A: ExclusiveAllocator> {
+    inner: UnsafeCell<A>,
+    hooks: [Cell<Option<&'a dyn ReclaimHook>>; MAX_RECLAIM_HOOKS],
+    in_hooks: Cell<bool>,
+    /// The block belongs to the inner allocator rather than to anything nameable, so its
+    /// lifetime is erased. It's released in `into_inner`, once the reserve is gone.
+    reserve: UnsafeCell<Option<Reserve<'static>>>,
+}
+
+impl<'a, A: ExclusiveAllocator> Reclaiming<'a, A> {
+    /// Wraps `inner`, setting aside `reserve` for high-priority allocations. No reserve is
+    /// kept if it can't be allocated.
+    #[inline]
+    pub fn new(mut inner: A, reserve: Layout) -> Self {
+        let reserve = (reserve.size() > 0)
+            .then(|| inner.alloc_exclusive(reserve).ok())
+            .flatten()
+            .map(|block| {
+                // Safety: the block stays allocated until the reserve is released in `into_inner`,
+                // and nothing else points into it.
+                let slice = unsafe { &mut *(block.as_ptr() as *mut [MaybeUninit<u8>]) };
+                let ugb = UnalignedGenericBuffer::from_backing_allocation(BackingAllocation::from_unique_uninit_slice(slice));
+
+                Reserve {
+                    alloc: StackAllocator::from_unaligned_generic_buffer(ugb),
+                    block: block.cast(),
+                    layout: reserve,
+                    live: 0,
+                }
+            });
+
+        Reclaiming {
+            inner: UnsafeCell::new(inner),
+            hooks: [const { Cell::new(None) }; MAX_RECLAIM_HOOKS],
+            in_hooks: Cell::new(false),
+            reserve: UnsafeCell::new(reserve),
+        }
+    }
+
+    /// Registers a hook, run after the ones registered before it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the hook if [`MAX_RECLAIM_HOOKS`] are already registered.
+    #[inline]
+    pub fn register_hook(&self, hook: &'a dyn ReclaimHook) -> Result<(), &'a dyn ReclaimHook> {
+        match self.hooks.iter().find(|slot| slot.get().is_none()) {
+            Some(slot) => {
+                slot.set(Some(hook));
+                Ok(())
+            }
+            None => Err(hook),
+        }
+    }
+
+    /// Returns the size of the emergency reserve, or 0 if there is none.
+    #[inline]
+    #[must_use]
+    pub fn reserve_capacity(&self) -> usize {
+        unsafe { (*self.reserve.get()).as_ref() }.map_or(0, |reserve| reserve.alloc.capacity())
+    }
+
+    /// Returns the amount of reserve bytes in use by high-priority allocations.
+    #[inline]
+    #[must_use]
+    pub fn reserve_used(&self) -> usize {
+        unsafe { (*self.reserve.get()).as_ref() }.map_or(0, |reserve| reserve.alloc.pos())
+    }
+
+    #[inline]
+    fn inner_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Safety: no reference to the inner allocator is held across hook calls.
+        unsafe { (*self.inner.get()).alloc_exclusive(layout) }
+    }
+
+    fn alloc_after_reclaim(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.in_hooks.replace(true) {
+            return Err(AllocError);
+        }
+
+        let mut result = Err(AllocError);
+        for hook in self.hooks.iter().map_while(Cell::get) {
+            if hook.reclaim(layout.size()) > 0 {
+                result = self.inner_alloc(layout);
+                if result.is_ok() {
+                    break;
+                }
+            }
+        }
+
+        self.in_hooks.set(false);
+        result
+    }
+
+    fn alloc_from_reserve(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // a zero-size block could sit at the end of the reserve, where `dealloc` would
+        // take it for one of the inner allocator
+        if layout.size() == 0 {
+            return Err(AllocError);
+        }
+        let reserve = unsafe { (*self.reserve.get()).as_mut() }.ok_or(AllocError)?;
+        let block = reserve.alloc.allocate(layout)?;
+        reserve.live += 1;
+        Ok(block)
+    }
+
+    /// Allocates from the inner allocator, then after running the reclaim hooks, and
+    /// finally from the reserve if `priority` is [`Priority::High`].
+    #[inline]
+    pub fn alloc(&self, layout: Layout, priority: Priority) -> Result<NonNull<[u8]>, AllocError> {
+        self.inner_alloc(layout)
+            .or_else(|_| self.alloc_after_reclaim(layout))
+            .or_else(|err| match priority {
+                Priority::High => self.alloc_from_reserve(layout),
+                Priority::Normal => Err(err),
+            })
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this allocator with the same `layout`, and must not
+    /// be used afterwards.
+    #[inline]
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        if let Some(reserve) = unsafe { (*self.reserve.get()).as_mut() } {
+            let start = reserve.block.as_ptr().addr();
+            if (start..start + reserve.layout.size()).contains(&ptr.as_ptr().addr()) {
+                unsafe { reserve.alloc.deallocate(ptr, layout) };
+                reserve.live -= 1;
+                // blocks freed out of order are reclaimed once the reserve is unused
+                if reserve.live == 0 {
+                    reserve.alloc.reset();
+                }
+                return;
+            }
+        }
+
+        unsafe { (*self.inner.get()).dealloc_exclusive(ptr, layout) };
+    }
+
+    /// Returns the inner allocator, handing the reserve back to it unless it is in use.
+    #[inline]
+    pub fn into_inner(self) -> A {
+        let mut inner = self.inner.into_inner();
+        if let Some(reserve) = self.reserve.into_inner()
+            && reserve.live == 0
+        {
+            unsafe { inner.dealloc_exclusive(reserve.block, reserve.layout) };
+        }
+        inner
+    }
+
+    /// Returns a handle allocating with [`Priority::High`].
+    #[cfg(feature = "allocator_api")]
+    #[inline]
+    #[must_use]
+    pub const fn high_priority(&self) -> HighPriority<'_, 'a, A> {
+        HighPriority { alloc: self }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: ExclusiveAllocator> Allocator for Reclaiming<'_, A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.alloc(layout, Priority::Normal).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.dealloc(ptr, layout) };
+    }
+}
+
+/// A [`Reclaiming`] handle whose allocations may use the emergency reserve.
+#[cfg(feature = "allocator_api")]
+pub struct HighPriority<'r, 'a, A: ExclusiveAllocator> {
+    alloc: &'r Reclaiming<'a, A>,
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: ExclusiveAllocator> Allocator for HighPriority<'_, '_, A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.alloc.alloc(layout, Priority::High).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.alloc.dealloc(ptr, layout) };
+    }
+}
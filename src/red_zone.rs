@@ -0,0 +1,272 @@
+use crate::const_allocator_shared::AllocError;
+use crate::leak_check::{LiveAlloc, LiveTable};
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::Layout;
+use core::fmt;
+use core::panic::Location;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+#[cfg(feature = "allocator_api")]
+use core::ptr;
+
+/// Minimum number of guard bytes on each side of a block. The front guard is rounded up
+/// to the alignment of the block.
+pub const RED_ZONE_BYTES: usize = 16;
+
+/// The byte guard bytes are filled with.
+pub const RED_ZONE_PATTERN: u8 = 0xFD;
+
+/// A guard byte found overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Corruption {
+    pub ptr: NonNull<u8>,
+    pub layout: Layout,
+    /// Offset of the overwritten byte from `ptr`, negative in the front guard and at least
+    /// `layout.size()` in the back guard. When several bytes are overwritten, it is the one
+    /// closest to the block.
+    pub offset: isize,
+    pub found: u8,
+    /// The allocation the block belongs to, if it was tracked.
+    pub alloc: Option<LiveAlloc>,
+}
+
+impl fmt::Display for Corruption {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = if self.offset < 0 { "underrun" } else { "overrun" };
+        write!(
+            f,
+            "red zone {side} at offset {} of the {} byte block at {:p}: found {:#04x} instead of {:#04x}",
+            self.offset,
+            self.layout.size(),
+            self.ptr,
+            self.found,
+            RED_ZONE_PATTERN
+        )?;
+        if let Some(alloc) = self.alloc {
+            write!(f, ", allocation {alloc}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Surrounds every block of the wrapped allocator with guard bytes to catch overruns.
+///
+/// [`RED_ZONE_BYTES`] of [`RED_ZONE_PATTERN`] go in front of and behind each block, and
+/// are checked when the block is freed, grown or shrunk, or on
+/// [`check_all`](Self::check_all).
+///
+/// Freeing or reallocating a corrupted block panics with a [`Corruption`] message. Up to
+/// `N` live allocations are tracked to be found by `check_all` and named in reports.
+/// Blocks allocated while the table is full go untracked until they are freed, but their
+/// guards are still checked then.
+///
+/// It forwards whichever of [`Allocator`](core::alloc::Allocator) and
+/// [`ExclusiveAllocator`] `A` implements.
+pub struct RedZone<A, const N: usize> {
+    inner: A,
+    live: LiveTable<N>,
+}
+
+impl<A, const N: usize> RedZone<A, N> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            live: LiveTable::new(),
+        }
+    }
+
+    #[inline]
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Returns the number of allocations that weren't tracked because the table was full.
+    /// Their guards are still checked when they are freed.
+    #[inline]
+    #[must_use]
+    pub const fn untracked(&self) -> usize {
+        self.live.untracked()
+    }
+
+    /// Checks the guards of every tracked live block, returning the first corruption found.
+    #[inline]
+    pub fn check_all(&self) -> Result<(), Corruption> {
+        self.live
+            .iter()
+            .try_for_each(|alloc| unsafe { Self::verify(alloc.ptr, alloc.layout, Some(alloc)) })
+    }
+
+    /// Returns the layout including the guards, and the offset of the user block.
+    #[inline]
+    fn guarded(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        let front = RED_ZONE_BYTES.next_multiple_of(layout.align());
+        let size = front
+            .checked_add(layout.size())
+            .and_then(|size| size.checked_add(RED_ZONE_BYTES))
+            .ok_or(AllocError)?;
+        let full = Layout::from_size_align(size, layout.align()).map_err(|_| AllocError)?;
+        Ok((full, front))
+    }
+
+    /// Fills the guards around the user block inside `base` and returns the user block.
+    #[inline]
+    const unsafe fn arm(base: NonNull<u8>, layout: Layout, front: usize) -> NonNull<u8> {
+        let user = unsafe { base.add(front) };
+        unsafe {
+            base.write_bytes(RED_ZONE_PATTERN, front);
+            user.add(layout.size()).write_bytes(RED_ZONE_PATTERN, RED_ZONE_BYTES);
+        }
+        user
+    }
+
+    #[inline]
+    #[track_caller]
+    fn track(&self, user: NonNull<u8>, layout: Layout) -> NonNull<[u8]> {
+        self.live.insert(user, layout, Location::caller());
+        NonNull::slice_from_raw_parts(user, layout.size())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if a guard byte of `ptr` was overwritten.
+    #[inline]
+    unsafe fn check(&self, ptr: NonNull<u8>, layout: Layout) {
+        if let Err(corruption) = unsafe { Self::verify(ptr, layout, self.live.get(ptr)) } {
+            panic!("{corruption}");
+        }
+    }
+
+    unsafe fn verify(ptr: NonNull<u8>, layout: Layout, alloc: Option<LiveAlloc>) -> Result<(), Corruption> {
+        let front = RED_ZONE_BYTES.next_multiple_of(layout.align());
+        let corrupted = |offset: isize| {
+            let found = unsafe { ptr.offset(offset).read() };
+            (found != RED_ZONE_PATTERN).then_some(Corruption {
+                ptr,
+                layout,
+                offset,
+                found,
+                alloc,
+            })
+        };
+
+        let front_bytes = (1..=front.cast_signed()).map(|back| -back);
+        let back_bytes = (0..RED_ZONE_BYTES.cast_signed()).map(|i| layout.size().cast_signed() + i);
+        match front_bytes.chain(back_bytes).find_map(corrupted) {
+            Some(corruption) => Err(corruption),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<A: Allocator, const N: usize> RedZone<A, N> {
+    #[inline]
+    #[track_caller]
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, StdAllocError> {
+        let (old_full, old_front) = Self::guarded(old_layout).map_err(|_| StdAllocError)?;
+        let (full, front) = Self::guarded(new_layout).map_err(|_| StdAllocError)?;
+        unsafe { self.check(ptr, old_layout) };
+
+        let base = if zeroed {
+            self.inner.allocate_zeroed(full)
+        } else {
+            self.inner.allocate(full)
+        }?;
+        let user = unsafe { Self::arm(base.cast(), new_layout, front) };
+        unsafe { ptr::copy_nonoverlapping(ptr.as_ptr(), user.as_ptr(), old_layout.size().min(new_layout.size())) };
+
+        // a tracked block keeps its place in the allocation order
+        let block = if self.live.moved(ptr, user, new_layout) {
+            NonNull::slice_from_raw_parts(user, new_layout.size())
+        } else {
+            self.track(user, new_layout)
+        };
+        unsafe { self.inner.deallocate(ptr.sub(old_front), old_full) };
+        Ok(block)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: Allocator, const N: usize> Allocator for RedZone<A, N> {
+    #[inline]
+    #[track_caller]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let (full, front) = Self::guarded(layout).map_err(|_| StdAllocError)?;
+        let base = self.inner.allocate(full)?;
+        Ok(self.track(unsafe { Self::arm(base.cast(), layout, front) }, layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let (full, front) = Self::guarded(layout).map_err(|_| StdAllocError)?;
+        let base = self.inner.allocate_zeroed(full)?;
+        Ok(self.track(unsafe { Self::arm(base.cast(), layout, front) }, layout))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let Ok((full, front)) = Self::guarded(layout) else {
+            return;
+        };
+        unsafe { self.check(ptr, layout) };
+        self.live.remove(ptr);
+        unsafe { self.inner.deallocate(ptr.sub(front), full) };
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        unsafe { self.realloc(ptr, old_layout, new_layout, false) }
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        unsafe { self.realloc(ptr, old_layout, new_layout, true) }
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        unsafe { self.realloc(ptr, old_layout, new_layout, false) }
+    }
+}
+
+unsafe impl<A: ExclusiveAllocator, const N: usize> ExclusiveAllocator for RedZone<A, N> {
+    #[inline]
+    #[track_caller]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let (full, front) = Self::guarded(layout)?;
+        let base = self.inner.alloc_exclusive(full)?;
+        Ok(self.track(unsafe { Self::arm(base.cast(), layout, front) }, layout))
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let Ok((full, front)) = Self::guarded(layout) else {
+            return;
+        };
+        unsafe { self.check(ptr, layout) };
+        self.live.remove(ptr);
+        unsafe { self.inner.dealloc_exclusive(ptr.sub(front), full) };
+    }
+}
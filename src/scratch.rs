@@ -0,0 +1,179 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::slice_allocator::StackAllocator;
+use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// How deep [`Scratch`] guards can nest on one arena.
+const MAX_DEPTH: usize = 16;
+
+/// One bump arena of a [`ScratchArenas`] pair. Memory is only handed out through
+/// [`Scratch`] guards.
+pub struct ScratchArena<'buf> {
+    alloc: UnsafeCell<StackAllocator<'buf>>,
+    depth: Cell<usize>,
+    /// The marks of guards dropped while a nested guard was still alive, by depth.
+    released: [Cell<Option<usize>>; MAX_DEPTH],
+}
+
+impl<'buf> ScratchArena<'buf> {
+    #[inline]
+    const fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        let ugb = UnalignedGenericBuffer::from_backing_allocation(mem);
+        ScratchArena {
+            alloc: UnsafeCell::new(StackAllocator::from_unaligned_generic_buffer(ugb)),
+            depth: Cell::new(0),
+            released: [const { Cell::new(None) }; MAX_DEPTH],
+        }
+    }
+
+    /// Returns the amount of bytes in use by all live scratch guards of this arena.
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> usize {
+        unsafe { (*self.alloc.get()).pos() }
+    }
+}
+
+/// A pair of scratch arenas for one thread.
+///
+/// Temporaries of a function are allocated in a scratch arena that is not the one its
+/// results go to, so they never interleave with the results and can be rewound as soon
+/// as the function returns.
+pub struct ScratchArenas<'buf> {
+    arenas: [ScratchArena<'buf>; 2],
+}
+
+impl<'buf> ScratchArenas<'buf> {
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
+        ScratchArenas::from_backing_allocation(BackingAllocation::from_unique_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_unique_uninit_slice(slice: &'buf mut [MaybeUninit<u8>]) -> Self {
+        ScratchArenas::from_backing_allocation(BackingAllocation::from_unique_uninit_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        let mid = mem.len() / 2;
+        let (first, second) = mem.split_at(mid);
+        ScratchArenas {
+            arenas: [
+                ScratchArena::from_backing_allocation(first),
+                ScratchArena::from_backing_allocation(second),
+            ],
+        }
+    }
+
+    /// Returns a scratch guard on an arena that isn't in `conflicts`, or `None` if both
+    /// arenas conflict or already have too many guards nested on them.
+    ///
+    /// Pass the arena the caller's output lives in, see [`Scratch::arena`].
+    #[inline]
+    #[must_use]
+    pub fn get_scratch(&self, conflicts: &[&ScratchArena<'buf>]) -> Option<Scratch<'_, 'buf>> {
+        let arena = self
+            .arenas
+            .iter()
+            .filter(|arena| arena.depth.get() < MAX_DEPTH)
+            .find(|arena| !conflicts.iter().any(|conflict| ptr::eq(*arena, *conflict)))?;
+
+        let depth = arena.depth.get() + 1;
+        arena.depth.set(depth);
+
+        Some(Scratch {
+            arena,
+            mark: arena.used(),
+            depth,
+        })
+    }
+}
+
+/// A scratch allocation scope. Everything allocated through it is rewound when it drops.
+///
+/// Scratch guards on the same arena nest: while an inner guard is alive, allocating
+/// through an outer one fails, as the inner rewind would reclaim that memory too.
+pub struct Scratch<'a, 'buf> {
+    arena: &'a ScratchArena<'buf>,
+    mark: usize,
+    depth: usize,
+}
+
+impl<'buf> Scratch<'_, 'buf> {
+    /// Returns the arena this guard allocates in, to be passed as a conflict to
+    /// [`ScratchArenas::get_scratch`].
+    #[inline]
+    #[must_use]
+    pub const fn arena(&self) -> &ScratchArena<'buf> {
+        self.arena
+    }
+
+    #[inline]
+    const fn is_innermost(&self) -> bool {
+        self.arena.depth.get() == self.depth
+    }
+
+    #[inline]
+    pub fn alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.is_innermost() {
+            return Err(AllocError);
+        }
+
+        let allocator: &mut StackAllocator = unsafe { &mut *self.arena.alloc.get() };
+        allocator.allocate(layout)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl Allocator for Scratch<'_, '_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.alloc(layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.is_innermost() {
+            let allocator: &mut StackAllocator = unsafe { &mut *self.arena.alloc.get() };
+            unsafe { allocator.deallocate(ptr, layout) };
+        }
+    }
+}
+
+impl Drop for Scratch<'_, '_> {
+    #[inline]
+    fn drop(&mut self) {
+        let arena = self.arena;
+        if !self.is_innermost() {
+            // rewound along with the innermost guard once every nested one is gone
+            arena.released[self.depth - 1].set(Some(self.mark));
+            return;
+        }
+
+        let mut depth = self.depth - 1;
+        let mut mark = self.mark;
+        while let Some(outer) = depth.checked_sub(1).and_then(|index| arena.released[index].take()) {
+            depth -= 1;
+            mark = outer;
+        }
+
+        let allocator: &mut StackAllocator = unsafe { &mut *arena.alloc.get() };
+        allocator.rewind(mark);
+        arena.depth.set(depth);
+    }
+}
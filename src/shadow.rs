@@ -0,0 +1,284 @@
+use crate::const_allocator_shared::AllocError;
+use crate::leak_check::{LiveAlloc, LiveTable};
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::fmt;
+use core::panic::Location;
+use core::ptr::NonNull;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// A pointer or layout passed to [`ShadowChecked`] that breaks the allocator contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misuse {
+    /// The pointer was never handed out, or was freed too long ago to be remembered.
+    Unknown { ptr: NonNull<u8> },
+    /// The pointer was already freed.
+    DoubleFree { ptr: NonNull<u8>, alloc: LiveAlloc },
+    /// The pointer is `offset` bytes into a live block.
+    Interior { ptr: NonNull<u8>, offset: usize, alloc: LiveAlloc },
+    /// The layout size differs from the one the block was allocated with.
+    SizeMismatch { layout: Layout, alloc: LiveAlloc },
+    /// The layout alignment differs from the one the block was allocated with.
+    AlignMismatch { layout: Layout, alloc: LiveAlloc },
+}
+
+impl fmt::Display for Misuse {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Unknown { ptr } => write!(f, "{ptr:p} was never allocated"),
+            Self::DoubleFree { ptr, alloc } => write!(f, "double free of {ptr:p}, allocation {alloc}"),
+            Self::Interior { ptr, offset, alloc } => {
+                write!(f, "{ptr:p} points {offset} bytes into allocation {alloc}")
+            }
+            Self::SizeMismatch { layout, alloc } => {
+                write!(f, "size {} doesn't match allocation {alloc}", layout.size())
+            }
+            Self::AlignMismatch { layout, alloc } => {
+                write!(f, "align {} doesn't match allocation {alloc}", layout.align())
+            }
+        }
+    }
+}
+
+/// Checks every pointer and layout given back to the wrapped allocator against a side
+/// table of the blocks it handed out.
+///
+/// Deallocating, growing or shrinking panics with a [`Misuse`] message when the pointer
+/// was never handed out, was already freed, points into the middle of a block, or comes
+/// with a different size or alignment than it was allocated with.
+///
+/// Up to `N` live blocks are tracked, and allocating more fails so that no block goes
+/// unchecked. The last `N` freed blocks are remembered to tell double frees apart from
+/// unknown pointers. `N` must be at least 1.
+///
+/// Blocks are handed out at exactly the requested size, even if the wrapped allocator
+/// returned more, so that their layout can be checked strictly.
+///
+/// Zero-size blocks aren't tracked, as they may share their address with another block,
+/// and are passed through unchecked.
+///
+/// It forwards whichever of [`Allocator`](core::alloc::Allocator) and
+/// [`ExclusiveAllocator`] `A` implements.
+pub struct ShadowChecked<A, const N: usize> {
+    inner: A,
+    live: LiveTable<N>,
+    freed: [Cell<Option<LiveAlloc>>; N],
+    next_freed: Cell<usize>,
+}
+
+impl<A, const N: usize> ShadowChecked<A, N> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A) -> Self {
+        const { assert!(N > 0, "ShadowChecked needs room for at least one block") };
+        Self {
+            inner,
+            live: LiveTable::new(),
+            freed: [const { Cell::new(None) }; N],
+            next_freed: Cell::new(0),
+        }
+    }
+
+    #[inline]
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Returns the live blocks, in no particular order.
+    #[inline]
+    pub fn live(&self) -> impl Iterator<Item = LiveAlloc> + '_ {
+        self.live.iter()
+    }
+
+    /// Checks that `ptr` and `layout` may be passed to `deallocate`, returning the live
+    /// block they describe.
+    #[inline]
+    pub fn check(&self, ptr: NonNull<u8>, layout: Layout) -> Result<LiveAlloc, Misuse> {
+        if let Some(alloc) = self.live.get(ptr) {
+            return if alloc.layout.size() != layout.size() {
+                Err(Misuse::SizeMismatch { layout, alloc })
+            } else if alloc.layout.align() != layout.align() {
+                Err(Misuse::AlignMismatch { layout, alloc })
+            } else {
+                Ok(alloc)
+            };
+        }
+        if let Some(alloc) = self.live.containing(ptr) {
+            let offset = ptr.addr().get() - alloc.ptr.addr().get();
+            return Err(Misuse::Interior { ptr, offset, alloc });
+        }
+        match self.freed(ptr).and_then(Cell::get) {
+            Some(alloc) => Err(Misuse::DoubleFree { ptr, alloc }),
+            None => Err(Misuse::Unknown { ptr }),
+        }
+    }
+
+    fn freed(&self, ptr: NonNull<u8>) -> Option<&Cell<Option<LiveAlloc>>> {
+        self.freed.iter().find(|entry| entry.get().is_some_and(|alloc| alloc.ptr == ptr))
+    }
+
+    /// Checks a block given back to the allocator, returning `None` for zero-size ones.
+    #[inline]
+    #[track_caller]
+    fn checked(&self, ptr: NonNull<u8>, layout: Layout) -> Option<LiveAlloc> {
+        if layout.size() == 0 {
+            return None;
+        }
+        match self.check(ptr, layout) {
+            Ok(alloc) => Some(alloc),
+            Err(misuse) => panic!("{misuse}"),
+        }
+    }
+
+    /// Returns whether a new block of `layout` can be tracked.
+    #[inline]
+    fn has_room(&self, layout: Layout) -> bool {
+        layout.size() == 0 || !self.live.is_full()
+    }
+
+    /// Records a new block, trimmed to `layout` so that it is only ever given back with
+    /// the size it is tracked at.
+    #[inline]
+    #[track_caller]
+    fn track(&self, block: NonNull<[u8]>, layout: Layout) -> NonNull<[u8]> {
+        if layout.size() == 0 {
+            return block;
+        }
+        let ptr = block.cast::<u8>();
+        if let Some(entry) = self.freed(ptr) {
+            entry.set(None);
+        }
+        self.live.insert(ptr, layout, Location::caller());
+        NonNull::slice_from_raw_parts(ptr, layout.size())
+    }
+
+    fn forget(&self, alloc: LiveAlloc) {
+        self.live.remove(alloc.ptr);
+        self.remember_freed(alloc);
+    }
+
+    fn remember_freed(&self, alloc: LiveAlloc) {
+        let next = self.next_freed.get();
+        self.freed[next].set(Some(alloc));
+        self.next_freed.set((next + 1) % N);
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<A: Allocator, const N: usize> ShadowChecked<A, N> {
+    #[inline]
+    fn reserve(&self, layout: Layout) -> Result<(), StdAllocError> {
+        if self.has_room(layout) { Ok(()) } else { Err(StdAllocError) }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn moved(&self, alloc: Option<LiveAlloc>, block: NonNull<[u8]>, new_layout: Layout) -> NonNull<[u8]> {
+        let Some(alloc) = alloc else {
+            return self.track(block, new_layout);
+        };
+        if new_layout.size() == 0 {
+            self.forget(alloc);
+            return block;
+        }
+
+        let ptr = block.cast::<u8>();
+        if ptr != alloc.ptr {
+            if let Some(entry) = self.freed(ptr) {
+                entry.set(None);
+            }
+            self.remember_freed(alloc);
+        }
+        // the block keeps its place in the allocation order
+        self.live.moved(alloc.ptr, ptr, new_layout);
+        NonNull::slice_from_raw_parts(ptr, new_layout.size())
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: Allocator, const N: usize> Allocator for ShadowChecked<A, N> {
+    #[inline]
+    #[track_caller]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.reserve(layout)?;
+        Ok(self.track(self.inner.allocate(layout)?, layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        self.reserve(layout)?;
+        Ok(self.track(self.inner.allocate_zeroed(layout)?, layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if let Some(alloc) = self.checked(ptr, layout) {
+            self.forget(alloc);
+        }
+        unsafe { self.inner.deallocate(ptr, layout) };
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let alloc = self.checked(ptr, old_layout);
+        if alloc.is_none() {
+            self.reserve(new_layout)?;
+        }
+        let block = unsafe { self.inner.grow(ptr, old_layout, new_layout) }?;
+        Ok(self.moved(alloc, block, new_layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let alloc = self.checked(ptr, old_layout);
+        if alloc.is_none() {
+            self.reserve(new_layout)?;
+        }
+        let block = unsafe { self.inner.grow_zeroed(ptr, old_layout, new_layout) }?;
+        Ok(self.moved(alloc, block, new_layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let alloc = self.checked(ptr, old_layout);
+        let block = unsafe { self.inner.shrink(ptr, old_layout, new_layout) }?;
+        Ok(self.moved(alloc, block, new_layout))
+    }
+}
+
+unsafe impl<A: ExclusiveAllocator, const N: usize> ExclusiveAllocator for ShadowChecked<A, N> {
+    #[inline]
+    #[track_caller]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.has_room(layout) {
+            return Err(AllocError);
+        }
+        let block = self.inner.alloc_exclusive(layout)?;
+        Ok(self.track(block, layout))
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        if let Some(alloc) = self.checked(ptr, layout) {
+            self.forget(alloc);
+        }
+        unsafe { self.inner.dealloc_exclusive(ptr, layout) };
+    }
+}
@@ -1,11 +1,12 @@
 use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::poison::PoisonPolicy;
 use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
 use core::alloc::Layout;
 use core::cell::UnsafeCell;
 use core::mem::MaybeUninit;
 use core::ptr;
 use core::ptr::NonNull;
-use core::slice;
 
 #[cfg(feature = "allocator_api")]
 use core::alloc::AllocError as StdAllocError;
@@ -15,6 +16,123 @@ use core::alloc::Allocator;
 pub struct StackAllocator<'buf> {
     mem: UnalignedGenericBuffer<'buf, u8>,
     pos: usize,
+    /// The highest position ever reached. Bytes between `pos` and it have been freed.
+    high_water: usize,
+    poison: PoisonPolicy,
+}
+
+impl<'buf> StackAllocator<'buf> {
+    #[inline]
+    #[must_use]
+    pub const fn from_unaligned_generic_buffer(mem: UnalignedGenericBuffer<'buf, u8>) -> Self {
+        StackAllocator { mem, pos: 0, high_water: 0, poison: PoisonPolicy::DEFAULT }
+    }
+
+    /// Replaces the [`PoisonPolicy::DEFAULT`] the allocator starts with.
+    #[inline]
+    #[must_use]
+    pub const fn with_poison_policy(mut self, policy: PoisonPolicy) -> Self {
+        self.poison = policy;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn poison_policy(&self) -> PoisonPolicy {
+        self.poison
+    }
+
+    /// Returns the offset of the first byte past the latest allocation.
+    #[inline]
+    #[must_use]
+    pub const fn pos(&self) -> usize {
+        self.pos
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.mem.unaligned_len()
+    }
+
+    /// Bumps the position past a new block of `layout`.
+    #[inline]
+    pub fn allocate(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let align = layout.align();
+        let size = layout.size();
+
+        // the aligned address may lie past the buffer, so only compare addresses until the
+        // block is known to fit
+        let base = self.mem.as_unaligned_mut_ptr();
+        let offset = self.mem.as_next_aligned_mut_ptr_for(self.pos, align).addr() - base.addr();
+
+        // check if the block ends inside the buffer
+        if offset > self.capacity() || size > self.capacity() - offset {
+            return Err(AllocError);
+        }
+
+        let start = unsafe { base.add(offset) };
+        let slice = ptr::slice_from_raw_parts_mut(start, size);
+        let Some(nn) = NonNull::new(slice) else { return Err(AllocError) };
+
+        unsafe { self.poison_new_block(start, offset, size) };
+        self.pos = offset + size;
+        self.high_water = self.high_water.max(self.pos);
+        Ok(nn)
+    }
+
+    /// Checks the reused bytes of a new block and fills it according to the poison policy.
+    /// Fresh bytes get the free byte first, as if they had been freed before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the policy verifies reused bytes and one of them was overwritten.
+    #[inline]
+    unsafe fn poison_new_block(&self, start: *mut u8, offset: usize, size: usize) {
+        // bytes below the high water mark were handed out and freed before
+        let reused = self.high_water.saturating_sub(offset).min(size);
+        if let Some(i) = unsafe { self.poison.find_overwritten(start, reused) } {
+            panic!("freed byte at offset {} was written to after being freed", offset + i);
+        }
+        unsafe { self.poison.on_free(start.add(reused), size - reused) };
+        unsafe { self.poison.on_alloc(start, size) };
+    }
+
+    /// Returns a block to the allocator. Only the latest block is actually reclaimed.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `layout` must describe a block allocated by this allocator.
+    #[inline]
+    pub const unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let offset = unsafe { ptr.as_ptr().offset_from_unsigned(self.mem.as_unaligned_ptr()) };
+        let size = layout.size();
+
+        unsafe { self.poison.on_free(self.mem.as_unaligned_mut_ptr().add(offset), size) };
+
+        // if the pointer is at the end of the buffer, we can just update the position
+        if offset + size == self.pos {
+            self.pos = offset;
+        }
+
+        // otherwise, we can't really deallocate from the middle of the buffer
+    }
+
+    /// Moves the position back to `pos`, reclaiming everything allocated after it.
+    /// Positions past the current one are ignored.
+    #[inline]
+    pub const fn rewind(&mut self, pos: usize) {
+        if pos < self.pos {
+            unsafe { self.poison.on_free(self.mem.as_unaligned_mut_ptr().add(pos), self.pos - pos) };
+            self.pos = pos;
+        }
+    }
+
+    /// Makes the whole buffer available again.
+    #[inline]
+    pub const fn reset(&mut self) {
+        self.rewind(0);
+    }
 }
 
 pub struct SingleThreadedSliceAllocator<'buf> {
@@ -59,8 +177,39 @@ impl<'buf> SingleThreadedSliceAllocator<'buf> {
         SingleThreadedSliceAllocator::from_raw_parts(mem, 0)
     }
 
+    /// Returns the offset of the first byte past the latest allocation.
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> usize {
+        unsafe { (*self.alloc.get()).pos() }
+    }
+
+    /// Moves the allocation position back to `pos`, reclaiming everything allocated after it.
+    ///
+    /// # Safety
+    ///
+    /// No allocation made after the allocator was at `pos` may be used afterwards.
+    #[inline]
+    pub const unsafe fn rewind(&self, pos: usize) {
+        unsafe { (*self.alloc.get()).rewind(pos) };
+    }
+
+    /// Replaces the [`PoisonPolicy::DEFAULT`] the allocator starts with.
+    #[inline]
+    #[must_use]
+    pub const fn with_poison_policy(self, policy: PoisonPolicy) -> Self {
+        let alloc = self.alloc.into_inner().with_poison_policy(policy);
+        SingleThreadedSliceAllocator { alloc: UnsafeCell::new(alloc) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn poison_policy(&self) -> PoisonPolicy {
+        unsafe { (*self.alloc.get()).poison_policy() }
+    }
+
     const fn from_raw_parts(mem: UnalignedGenericBuffer<'buf, u8>, pos: usize) -> Self {
-        let alloc = StackAllocator { mem, pos };
+        let alloc = StackAllocator { mem, pos, high_water: pos, poison: PoisonPolicy::DEFAULT };
         let usc = UnsafeCell::new(alloc);
         SingleThreadedSliceAllocator { alloc: usc }
     }
@@ -71,28 +220,7 @@ unsafe impl Allocator for SingleThreadedSliceAllocator<'_> {
     #[inline]
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
         let allocator: &mut StackAllocator = unsafe { &mut *self.alloc.get() };
-        let align = layout.align();
-        let size = layout.size();
-
-        let aligned_end = allocator
-            .mem
-            .as_next_aligned_mut_ptr_for(allocator.pos, align)
-            .map_addr(|addr| addr + size);
-
-        // check if the next valid address is in the buffer
-        let end_past_one = unsafe { allocator.mem.as_unaligned_ptr().add(allocator.mem.valid_len()) };
-
-        if aligned_end.cast_const() > end_past_one {
-            return Err(StdAllocError);
-        }
-
-        let start = aligned_end.map_addr(|addr| addr - size);
-        let slice = unsafe { slice::from_raw_parts_mut(start, size) };
-
-        let Some(nn) = NonNull::new(slice) else { return Err(StdAllocError) };
-
-        allocator.pos += size;
-        Ok(nn)
+        allocator.allocate(layout).map_err(|_| StdAllocError)
     }
 
     #[inline]
@@ -108,30 +236,6 @@ unsafe impl Allocator for SingleThreadedSliceAllocator<'_> {
     #[inline]
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
         let allocator: &mut StackAllocator = unsafe { &mut *self.alloc.get() };
-
-        let offset = unsafe { ptr.as_ptr().offset_from_unsigned(allocator.mem.as_unaligned_ptr()) };
-        let size = layout.size();
-
-        if cfg!(debug_assertions) {
-            // rewrite the contents of the buffer to 0xAA for better debugging experience.
-            let debug_ptr = unsafe { allocator.mem.as_unaligned_mut_ptr().add(offset) };
-
-            // ⚠️ debug_ptr may be unaligned!
-            // ⚠️ UB: ptr::write_bytes(debug_ptr, 0xAA, size);
-
-            let mut i = 0;
-            while i < size {
-                let byte_ptr = unsafe { debug_ptr.add(i) };
-                unsafe { byte_ptr.write_unaligned(0xAA) };
-                i += 1;
-            }
-        }
-
-        // if the pointer is at the end of the buffer, we can just update the position
-        if offset + size == allocator.pos {
-            allocator.pos = offset;
-        }
-
-        // otherwise, we can't really deallocate from the middle of the buffer
+        unsafe { allocator.deallocate(ptr, layout) };
     }
 }
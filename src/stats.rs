@@ -0,0 +1,234 @@
+use crate::const_allocator_shared::AllocError;
+use crate::locked_allocator::ExclusiveAllocator;
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::AllocError as StdAllocError;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// Number of buckets in the size histogram. Bucket `i` counts sizes in `2^i..2^(i+1)`,
+/// with zero-sized allocations in bucket 0.
+pub const HISTOGRAM_BUCKETS: usize = usize::BITS as usize;
+
+/// A copy of the counters of a [`Stats`] wrapper at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Bytes requested by live allocations.
+    pub current_bytes: usize,
+    /// The highest `current_bytes` ever reached.
+    pub peak_bytes: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    pub reallocs: usize,
+    pub failures: usize,
+    /// Bytes skipped to align blocks, see [`Stats`].
+    pub padding_bytes: usize,
+    /// Successful allocations and reallocations by requested size.
+    pub histogram: [usize; HISTOGRAM_BUCKETS],
+}
+
+/// Counts what goes through the wrapped allocator.
+///
+/// The wrapper forwards [`Allocator`](core::alloc::Allocator), [`GlobalAlloc`] and
+/// [`ExclusiveAllocator`] calls to whichever of them `A` implements. The counters are
+/// atomic, so the wrapper is `Sync` whenever `A` is.
+///
+/// Alignment padding is measured as the gap between the end of the previous block and the
+/// start of the next one when it is smaller than the alignment, which is exact for bump
+/// allocators and an estimate for others.
+pub struct Stats<A> {
+    inner: A,
+    current_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+    reallocs: AtomicUsize,
+    failures: AtomicUsize,
+    padding_bytes: AtomicUsize,
+    last_end: AtomicUsize,
+    histogram: [AtomicUsize; HISTOGRAM_BUCKETS],
+}
+
+#[inline]
+const fn bucket_of(size: usize) -> usize {
+    if size == 0 { 0 } else { size.ilog2() as usize }
+}
+
+impl<A> Stats<A> {
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            current_bytes: AtomicUsize::new(0),
+            peak_bytes: AtomicUsize::new(0),
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            reallocs: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            padding_bytes: AtomicUsize::new(0),
+            last_end: AtomicUsize::new(0),
+            histogram: [const { AtomicUsize::new(0) }; HISTOGRAM_BUCKETS],
+        }
+    }
+
+    #[inline]
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Returns the current counters. Counters updated concurrently with the snapshot may
+    /// be slightly out of sync with each other.
+    #[inline]
+    #[must_use]
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            current_bytes: self.current_bytes.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            allocs: self.allocs.load(Ordering::Relaxed),
+            frees: self.frees.load(Ordering::Relaxed),
+            reallocs: self.reallocs.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            padding_bytes: self.padding_bytes.load(Ordering::Relaxed),
+            histogram: self.histogram.each_ref().map(|bucket| bucket.load(Ordering::Relaxed)),
+        }
+    }
+
+    #[inline]
+    fn record_block(&self, ptr: *mut u8, layout: Layout) {
+        let start = ptr.addr();
+        let last_end = self.last_end.swap(start + layout.size(), Ordering::Relaxed);
+        if start >= last_end && start - last_end < layout.align() {
+            self.padding_bytes.fetch_add(start - last_end, Ordering::Relaxed);
+        }
+        self.histogram[bucket_of(layout.size())].fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn add_bytes(&self, size: usize) {
+        let current = self.current_bytes.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(current, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn record_alloc<T: ?Sized>(&self, result: Result<NonNull<T>, AllocError>, layout: Layout) -> Result<NonNull<T>, AllocError> {
+        match result {
+            Ok(ptr) => {
+                self.allocs.fetch_add(1, Ordering::Relaxed);
+                self.add_bytes(layout.size());
+                self.record_block(ptr.as_ptr().cast(), layout);
+            }
+            Err(_) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    #[inline]
+    fn record_free(&self, layout: Layout) {
+        self.frees.fetch_add(1, Ordering::Relaxed);
+        self.current_bytes.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn record_realloc<T: ?Sized>(&self, result: Result<NonNull<T>, AllocError>, old: Layout, new: Layout) -> Result<NonNull<T>, AllocError> {
+        match result {
+            Ok(ptr) => {
+                self.reallocs.fetch_add(1, Ordering::Relaxed);
+                self.current_bytes.fetch_sub(old.size(), Ordering::Relaxed);
+                self.add_bytes(new.size());
+                self.record_block(ptr.as_ptr().cast(), new);
+            }
+            Err(_) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: Allocator> Allocator for Stats<A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = self.inner.allocate(layout).map_err(|_| AllocError);
+        self.record_alloc(result, layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = self.inner.allocate_zeroed(layout).map_err(|_| AllocError);
+        self.record_alloc(result, layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.inner.deallocate(ptr, layout) };
+        self.record_free(layout);
+    }
+
+    #[inline]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.grow(ptr, old_layout, new_layout) }.map_err(|_| AllocError);
+        self.record_realloc(result, old_layout, new_layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.grow_zeroed(ptr, old_layout, new_layout) }.map_err(|_| AllocError);
+        self.record_realloc(result, old_layout, new_layout).map_err(|_| StdAllocError)
+    }
+
+    #[inline]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, StdAllocError> {
+        let result = unsafe { self.inner.shrink(ptr, old_layout, new_layout) }.map_err(|_| AllocError);
+        self.record_realloc(result, old_layout, new_layout).map_err(|_| StdAllocError)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Stats<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let result = NonNull::new(unsafe { self.inner.alloc(layout) }).ok_or(AllocError);
+        self.record_alloc(result, layout).map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        self.record_free(layout);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let result = NonNull::new(unsafe { self.inner.realloc(ptr, layout, new_size) }).ok_or(AllocError);
+        self.record_realloc(result, layout, new_layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+}
+
+unsafe impl<A: ExclusiveAllocator> ExclusiveAllocator for Stats<A> {
+    #[inline]
+    fn alloc_exclusive(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let result = self.inner.alloc_exclusive(layout);
+        self.record_alloc(result, layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc_exclusive(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.inner.dealloc_exclusive(ptr, layout) };
+        self.record_free(layout);
+    }
+}
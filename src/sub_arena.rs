@@ -0,0 +1,197 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::const_allocator_shared::AllocError;
+use crate::experimental_allocator::ExperimentalAllocator;
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell};
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use core::slice;
+
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+
+/// An allocator that can hand out whole regions of its memory to be used as the
+/// [`BackingAllocation`] of another allocator.
+///
+/// # Safety
+///
+/// A region returned by [`allocate_backing`](Self::allocate_backing) must be valid for
+/// reads and writes of its whole length, and must not be handed out again until it is
+/// passed back to [`release_backing`](Self::release_backing).
+pub unsafe trait BackingSource {
+    fn allocate_backing(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+    /// # Safety
+    ///
+    /// `ptr` and `layout` must match a region previously returned by
+    /// [`allocate_backing`](Self::allocate_backing) on this source, and the region must
+    /// not be used afterwards.
+    unsafe fn release_backing(&mut self, ptr: NonNull<u8>, layout: Layout);
+}
+
+unsafe impl<P: BackingSource + ?Sized> BackingSource for &mut P {
+    #[inline]
+    fn allocate_backing(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        (**self).allocate_backing(layout)
+    }
+
+    #[inline]
+    unsafe fn release_backing(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { (**self).release_backing(ptr, layout) }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: Allocator + ?Sized> BackingSource for &A {
+    #[inline]
+    fn allocate_backing(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate(layout).map_err(|_| AllocError)
+    }
+
+    #[inline]
+    unsafe fn release_backing(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.deallocate(ptr, layout) }
+    }
+}
+
+unsafe impl BackingSource for ExperimentalAllocator<'_> {
+    #[inline]
+    fn allocate_backing(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc_fitting(layout)
+    }
+
+    #[inline]
+    unsafe fn release_backing(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.free(ptr, layout) }
+    }
+}
+
+/// Placed in front of every region a [`SubArena`] carves out, to return it on drop.
+struct RegionHeader {
+    prev: Option<NonNull<Self>>,
+    next: Option<NonNull<Self>>,
+    layout: Layout,
+}
+
+/// A handle on a parent [`BackingSource`] carving regions out of it to back child
+/// allocators.
+///
+/// A [`SubArenaRegion`] goes back to the parent when it drops. The backing it lends
+/// borrows the region, which borrows the `SubArena`, so neither can be dropped while a
+/// child built on it is alive. The `SubArena` holds the parent, so a `&mut` parent can't
+/// be used or reset meanwhile. Regions that were leaked go back when the `SubArena` drops.
+///
+/// ```compile_fail
+/// # use slice_alloc::experimental_allocator::ExperimentalAllocator;
+/// # use slice_alloc::slice_allocator::SingleThreadedSliceAllocator;
+/// # use slice_alloc::sub_arena::SubArena;
+/// # use core::alloc::Layout;
+/// let mut memory = [0u8; 256];
+/// let mut parent = ExperimentalAllocator::from_unique_slice(&mut memory);
+/// let arena = SubArena::new(&mut parent);
+/// let mut region = arena.allocate_backing(Layout::new::<[u8; 64]>()).unwrap();
+/// let child = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(region.backing()) };
+/// drop(region);
+/// drop(child);
+/// ```
+pub struct SubArena<P: BackingSource> {
+    parent: RefCell<P>,
+    regions: Cell<Option<NonNull<RegionHeader>>>,
+}
+
+impl<P: BackingSource> SubArena<P> {
+    #[inline]
+    #[must_use]
+    pub const fn new(parent: P) -> Self {
+        Self { parent: RefCell::new(parent), regions: Cell::new(None) }
+    }
+
+    /// Allocates a region of `layout` from the parent, to be returned when the region
+    /// drops.
+    #[inline]
+    pub fn allocate_backing(&self, layout: Layout) -> Result<SubArenaRegion<'_, P>, AllocError> {
+        let (full, offset) = Layout::new::<RegionHeader>().extend(layout).map_err(|_| AllocError)?;
+        let region = self.parent.borrow_mut().allocate_backing(full)?;
+
+        let header = region.cast::<RegionHeader>();
+        let next = self.regions.get();
+        unsafe {
+            header.write(RegionHeader { prev: None, next, layout: full });
+        }
+        if let Some(next) = next {
+            unsafe { (*next.as_ptr()).prev = Some(header) };
+        }
+        self.regions.set(Some(header));
+
+        let data = unsafe { region.cast::<u8>().add(offset) };
+        Ok(SubArenaRegion {
+            arena: self,
+            header,
+            data: NonNull::slice_from_raw_parts(data, region.len() - offset),
+        })
+    }
+
+    /// Unlinks a region and returns it to the parent.
+    ///
+    /// # Safety
+    ///
+    /// `header` must be linked into this arena, and nothing may use the region afterwards.
+    unsafe fn release(&self, header: NonNull<RegionHeader>) {
+        let RegionHeader { prev, next, layout } = unsafe { header.read() };
+        match prev {
+            Some(prev) => unsafe { (*prev.as_ptr()).next = next },
+            None => self.regions.set(next),
+        }
+        if let Some(next) = next {
+            unsafe { (*next.as_ptr()).prev = prev };
+        }
+        unsafe { self.parent.borrow_mut().release_backing(header.cast(), layout) };
+    }
+}
+
+impl<P: BackingSource> Drop for SubArena<P> {
+    #[inline]
+    fn drop(&mut self) {
+        while let Some(header) = self.regions.get() {
+            unsafe { self.release(header) };
+        }
+    }
+}
+
+/// A region of a [`SubArena`], returned to the parent when dropped.
+pub struct SubArenaRegion<'a, P: BackingSource> {
+    arena: &'a SubArena<P>,
+    header: NonNull<RegionHeader>,
+    data: NonNull<[u8]>,
+}
+
+impl<P: BackingSource> SubArenaRegion<'_, P> {
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Lends the region as the backing of a child allocator, which can't outlive it.
+    #[inline]
+    pub const fn backing(&mut self) -> BackingAllocation<'_> {
+        // Safety: the bytes past the header are valid for reads and writes, and the
+        // mutable borrow makes the returned backing the only way to reach them.
+        let mem = unsafe { slice::from_raw_parts_mut(self.data.cast::<MaybeUninit<u8>>().as_ptr(), self.data.len()) };
+        BackingAllocation::from_unique_uninit_slice(mem)
+    }
+}
+
+impl<P: BackingSource> Drop for SubArenaRegion<'_, P> {
+    #[inline]
+    fn drop(&mut self) {
+        // Safety: the region is linked until now, and no backing borrowed from it is left.
+        unsafe { self.arena.release(self.header) };
+    }
+}
@@ -0,0 +1,358 @@
+use core::alloc::AllocError;
+use core::alloc::Allocator;
+use core::alloc::Layout;
+use core::array;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
+use core::cell::Cell;
+#[cfg(feature = "std")]
+use std::thread_local;
+
+/// Usage of one allocation category, as listed by [`TaggedAllocator::report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagUsage {
+    pub name: &'static str,
+    pub live_bytes: usize,
+    pub peak_bytes: usize,
+    /// Allocations ever made under this tag.
+    pub allocs: usize,
+}
+
+/// [`Allocator::grow`] or [`Allocator::shrink`] of the wrapped allocator.
+type Resize<A> = unsafe fn(&A, NonNull<u8>, Layout, Layout) -> Result<NonNull<[u8]>, AllocError>;
+
+struct TagCounters {
+    live_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    allocs: AtomicUsize,
+}
+
+/// Wraps an allocator and accounts every allocation to one of `TAGS` categories.
+///
+/// Allocations made through the wrapper itself go to the current scope tag set with
+/// [`with_tag`](Self::with_tag), and those made through a [`TagHandle`] go to its tag.
+/// The tag is kept in a word-sized header in front of each block, so a block is credited
+/// back to the right category however it is freed.
+///
+/// Every wrapper has its own scope tag, so nested [`with_tag`](Self::with_tag) calls on
+/// different wrappers don't affect each other. With the `std` feature, the scope tag is
+/// also per thread. Without it, it is shared by all threads using the wrapper. Tagged
+/// handles work with any number of threads either way.
+pub struct TaggedAllocator<A, const TAGS: usize> {
+    inner: A,
+    names: [&'static str; TAGS],
+    counters: [TagCounters; TAGS],
+    #[cfg(not(feature = "std"))]
+    current: AtomicUsize,
+}
+
+/// A `with_tag` call, linked to the ones it is nested in on the same thread.
+#[cfg(feature = "std")]
+struct Scope {
+    /// The address of the wrapper.
+    alloc: usize,
+    tag: usize,
+    outer: Option<NonNull<Self>>,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// The innermost `with_tag` call of this thread.
+    static SCOPE: Cell<Option<NonNull<Scope>>> = const { Cell::new(None) };
+}
+
+impl<A: Allocator, const TAGS: usize> TaggedAllocator<A, TAGS> {
+    /// Creates the wrapper with one name per tag. Tag 0 is the scope tag outside of any
+    /// [`with_tag`](Self::with_tag) call.
+    #[inline]
+    #[must_use]
+    pub const fn new(inner: A, names: [&'static str; TAGS]) -> Self {
+        assert!(TAGS > 0, "TaggedAllocator needs at least one tag");
+        Self {
+            inner,
+            names,
+            counters: [const {
+                TagCounters {
+                    live_bytes: AtomicUsize::new(0),
+                    peak_bytes: AtomicUsize::new(0),
+                    allocs: AtomicUsize::new(0),
+                }
+            }; TAGS],
+            #[cfg(not(feature = "std"))]
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs `f` with `tag` as the scope tag, restoring the previous one afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` isn't less than `TAGS`.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn with_tag<R, F: FnOnce() -> R>(&self, tag: usize, f: F) -> R {
+        struct Restore(Option<NonNull<Scope>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                SCOPE.set(self.0);
+            }
+        }
+
+        assert!(tag < TAGS, "tag out of range");
+        let scope = Scope { alloc: ptr::from_ref(self).addr(), tag, outer: SCOPE.get() };
+        // unlinks `scope` before it goes out of scope
+        let _restore = Restore(scope.outer);
+        SCOPE.set(Some(NonNull::from(&scope)));
+        f()
+    }
+
+    /// Runs `f` with `tag` as the scope tag, restoring the previous one afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` isn't less than `TAGS`.
+    #[inline]
+    #[cfg(not(feature = "std"))]
+    pub fn with_tag<R, F: FnOnce() -> R>(&self, tag: usize, f: F) -> R {
+        struct Restore<'a>(&'a AtomicUsize, usize);
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.0.store(self.1, Ordering::Relaxed);
+            }
+        }
+
+        assert!(tag < TAGS, "tag out of range");
+        let _restore = Restore(&self.current, self.current.swap(tag, Ordering::Relaxed));
+        f()
+    }
+
+    #[inline]
+    #[cfg(feature = "std")]
+    fn scope_tag(&self) -> usize {
+        let alloc = ptr::from_ref(self).addr();
+        // a thread being torn down has no scope
+        SCOPE
+            .try_with(|innermost| {
+                let mut next = innermost.get();
+                while let Some(scope) = next {
+                    // Safety: a scope is linked only while its `with_tag` call runs.
+                    let scope = unsafe { scope.as_ref() };
+                    if scope.alloc == alloc {
+                        return scope.tag;
+                    }
+                    next = scope.outer;
+                }
+                0
+            })
+            .unwrap_or(0)
+    }
+
+    #[inline]
+    #[cfg(not(feature = "std"))]
+    fn scope_tag(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Returns a handle that allocates under `tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` isn't less than `TAGS`.
+    #[inline]
+    #[must_use]
+    pub const fn tagged(&self, tag: usize) -> TagHandle<'_, A, TAGS> {
+        assert!(tag < TAGS, "tag out of range");
+        TagHandle { alloc: self, tag }
+    }
+
+    /// Returns the usage of `tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` isn't less than `TAGS`.
+    #[inline]
+    #[must_use]
+    pub fn usage(&self, tag: usize) -> TagUsage {
+        let counters = &self.counters[tag];
+        TagUsage {
+            name: self.names[tag],
+            live_bytes: counters.live_bytes.load(Ordering::Relaxed),
+            peak_bytes: counters.peak_bytes.load(Ordering::Relaxed),
+            allocs: counters.allocs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the usage of every tag, in tag order.
+    #[inline]
+    #[must_use]
+    pub fn report(&self) -> [TagUsage; TAGS] {
+        array::from_fn(|tag| self.usage(tag))
+    }
+
+    /// Returns the layout including the tag header, and the offset of the user block.
+    #[inline]
+    fn with_header(layout: Layout) -> Result<(Layout, usize), AllocError> {
+        Layout::new::<usize>().extend(layout).map_err(|_| AllocError)
+    }
+
+    #[inline]
+    fn alloc_tagged(&self, layout: Layout, tag: usize) -> Result<NonNull<[u8]>, AllocError> {
+        let (full, offset) = Self::with_header(layout)?;
+        let block = self.inner.allocate(full)?.cast::<u8>();
+
+        // Safety: the header word lies right before the user block, which is aligned to at
+        // least a word as the offset is.
+        let user = unsafe { block.add(offset) };
+        unsafe { user.cast::<usize>().sub(1).write(tag) };
+
+        let counters = &self.counters[tag];
+        counters.allocs.fetch_add(1, Ordering::Relaxed);
+        let live = counters.live_bytes.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        counters.peak_bytes.fetch_max(live, Ordering::Relaxed);
+
+        Ok(NonNull::slice_from_raw_parts(user, layout.size()))
+    }
+
+    /// Resizes a block with `resize`, one of the inner allocator's `grow` or `shrink`,
+    /// keeping its tag and counting it as the same allocation.
+    #[inline]
+    unsafe fn realloc_tagged(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        resize: Resize<A>,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let (old_full, old_offset) = Self::with_header(old_layout)?;
+        let (new_full, new_offset) = Self::with_header(new_layout)?;
+        let tag = unsafe { ptr.cast::<usize>().sub(1).read() };
+
+        let user = if old_offset == new_offset {
+            // the header is kept in place, as the front of the block is
+            let block = unsafe { resize(&self.inner, ptr.sub(old_offset), old_full, new_full) }?;
+            unsafe { block.cast::<u8>().add(new_offset) }
+        } else {
+            // the alignment changed, and with it where the header goes
+            let block = self.inner.allocate(new_full)?.cast::<u8>();
+            let user = unsafe { block.add(new_offset) };
+            unsafe {
+                user.cast::<usize>().sub(1).write(tag);
+                ptr::copy_nonoverlapping(ptr.as_ptr(), user.as_ptr(), old_layout.size().min(new_layout.size()));
+                self.inner.deallocate(ptr.sub(old_offset), old_full);
+            }
+            user
+        };
+
+        let counters = &self.counters[tag];
+        if let Some(grown) = new_layout.size().checked_sub(old_layout.size()) {
+            let live = counters.live_bytes.fetch_add(grown, Ordering::Relaxed) + grown;
+            counters.peak_bytes.fetch_max(live, Ordering::Relaxed);
+        } else {
+            let shrunk = old_layout.size() - new_layout.size();
+            counters.live_bytes.fetch_sub(shrunk, Ordering::Relaxed);
+        }
+
+        Ok(NonNull::slice_from_raw_parts(user, new_layout.size()))
+    }
+
+    #[inline]
+    unsafe fn dealloc_tagged(&self, ptr: NonNull<u8>, layout: Layout) {
+        let Ok((full, offset)) = Self::with_header(layout) else {
+            return;
+        };
+
+        let tag = unsafe { ptr.cast::<usize>().sub(1).read() };
+        self.counters[tag].live_bytes.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { self.inner.deallocate(ptr.sub(offset), full) };
+    }
+}
+
+unsafe impl<A: Allocator, const TAGS: usize> Allocator for TaggedAllocator<A, TAGS> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc_tagged(layout, self.scope_tag())
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.dealloc_tagged(ptr, layout) };
+    }
+
+    /// Keeps the tag of the block rather than using the current scope tag.
+    #[inline]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.realloc_tagged(ptr, old_layout, new_layout, A::grow) }
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = unsafe { self.realloc_tagged(ptr, old_layout, new_layout, A::grow) }?;
+        let tail = unsafe { block.cast::<u8>().add(old_layout.size()) };
+        unsafe { tail.write_bytes(0, new_layout.size() - old_layout.size()) };
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.realloc_tagged(ptr, old_layout, new_layout, A::shrink) }
+    }
+}
+
+/// An [`Allocator`] charging everything to one tag of a [`TaggedAllocator`].
+pub struct TagHandle<'a, A, const TAGS: usize> {
+    alloc: &'a TaggedAllocator<A, TAGS>,
+    tag: usize,
+}
+
+impl<A, const TAGS: usize> TagHandle<'_, A, TAGS> {
+    #[inline]
+    #[must_use]
+    pub const fn tag(&self) -> usize {
+        self.tag
+    }
+}
+
+impl<A, const TAGS: usize> Clone for TagHandle<'_, A, TAGS> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A, const TAGS: usize> Copy for TagHandle<'_, A, TAGS> {}
+
+unsafe impl<A: Allocator, const TAGS: usize> Allocator for TagHandle<'_, A, TAGS> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.alloc.alloc_tagged(layout, self.tag)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.alloc.dealloc_tagged(ptr, layout) };
+    }
+
+    /// Keeps the tag of the block rather than using the handle's.
+    #[inline]
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.alloc.realloc_tagged(ptr, old_layout, new_layout, A::grow) }
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let block = unsafe { self.alloc.realloc_tagged(ptr, old_layout, new_layout, A::grow) }?;
+        let tail = unsafe { block.cast::<u8>().add(old_layout.size()) };
+        unsafe { tail.write_bytes(0, new_layout.size() - old_layout.size()) };
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.alloc.realloc_tagged(ptr, old_layout, new_layout, A::shrink) }
+    }
+}
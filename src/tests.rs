@@ -140,6 +140,21 @@ const fn rust_intrinsic_const_allocate_can_escape_to_runtime_safely() -> &'stati
     unsafe { core::slice::from_raw_parts(allocation.cast::<u32>(), 2) }
 }
 
+#[cfg(feature = "allocator_api")]
+#[test]
+fn slice_allocator_padding_test() {
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u64; 8];
+    let alloc = unsafe { SingleThreadedSliceAllocator::from_unique_slice(rt_memory.as_mut_slice().align_to_mut::<u8>().1) };
+    let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+    let padded = alloc.allocate(Layout::new::<u32>()).unwrap().cast::<u8>();
+    let next = alloc.allocate(Layout::new::<u8>()).unwrap().cast::<u8>();
+    assert_eq!(padded.addr().get() % 4, 0);
+    assert!(next.addr().get() >= padded.addr().get() + 4, "blocks must not overlap");
+}
+
 #[cfg(feature = "allocator_api")]
 #[inline]
 pub fn slice_allocator_runtime() -> u64 {
@@ -177,3 +192,1508 @@ pub fn slice_allocator_runtime() -> u64 {
 
     if sum + sum2 + sum3 != 0 { 15 } else { 0 }
 }
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn sub_arena_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use crate::sub_arena::SubArena;
+    use alloc::boxed::Box;
+    use core::alloc::Layout;
+    use core::mem;
+
+    let mut rt_memory = vec![0u8; 1024];
+    let mut parent = ExperimentalAllocator::from_unique_slice(&mut rt_memory);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+
+    {
+        let arena = SubArena::new(&mut parent);
+        // each child takes more than half the parent, so its region must go back first
+        for round in 0..4u32 {
+            let mut region = arena.allocate_backing(layout).unwrap();
+            let child = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(region.backing()) };
+            let mut values: Vec<u32, &SingleThreadedSliceAllocator> = Vec::with_capacity_in(64, &child);
+            values.extend((0..64).map(|i| i + round));
+            assert_eq!(values.iter().sum::<u32>(), 2016 + 64 * round);
+        }
+
+        // the parent can't align regions past its own block alignment
+        assert!(arena.allocate_backing(Layout::from_size_align(24, 64).unwrap()).is_err());
+
+        // a leaked region goes back with the arena
+        mem::forget(arena.allocate_backing(layout).unwrap());
+    }
+
+    // the child's region went back to the parent, so it fits again
+    let again = parent.alloc(layout).unwrap();
+    unsafe { parent.free(again.cast(), layout) };
+
+    let nested_parent = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let arena = SubArena::new(&nested_parent);
+    let mut first = arena.allocate_backing(Layout::new::<[u8; 128]>()).unwrap();
+    let mut second = arena.allocate_backing(Layout::new::<[u8; 128]>()).unwrap();
+    let first = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(first.backing()) };
+    let second = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(second.backing()) };
+    let a = Box::new_in(1u64, &first);
+    let b = Box::new_in(2u64, &second);
+    assert_eq!(*a + *b, 3);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn split_backing_allocation_test() {
+    use crate::aligned_raw_slice::AlignedMutRawSlice;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+
+    let mut rt_memory = vec![0u8; 1040];
+    let mut backing = BackingAllocation::from_unique_slice(&mut rt_memory);
+
+    let (padding, aligned) = backing.reborrow().split_aligned_for::<u64>();
+    assert!(padding.len() < 8);
+    assert!(aligned.as_ptr().addr().is_multiple_of(8));
+
+    let mut chunks = aligned.chunks_exact(256);
+    assert_eq!(chunks.len(), 4);
+    let first = chunks.next().unwrap();
+    let second = chunks.next().unwrap();
+    assert_eq!(second.as_ptr().addr() - first.as_ptr().addr(), 256);
+    assert_eq!(chunks.into_remainder().len(), 1040 - padding.len() - 1024);
+
+    let a = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(first) };
+    let b = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(second) };
+    let mut xs: Vec<u64, &SingleThreadedSliceAllocator> = Vec::with_capacity_in(16, &a);
+    let mut ys: Vec<u64, &SingleThreadedSliceAllocator> = Vec::with_capacity_in(16, &b);
+    xs.extend(0..16);
+    ys.extend(16..32);
+    assert_eq!(xs.iter().chain(ys.iter()).sum::<u64>(), 496);
+    drop((xs, ys));
+
+    let (head, tail) = backing.split_at(10);
+    assert_eq!((head.len(), tail.len()), (10, 1030));
+    let (left, right) = UnalignedGenericBuffer::<u32>::from_backing_allocation(tail).split_at(5);
+    assert_eq!((left.unaligned_len(), right.unaligned_len()), (5, 252));
+
+    let words = AlignedMutRawSlice::<u32>::from_unaligned_generic_buffer(right);
+    let len = words.len();
+    let (front, back) = words.split_at(len / 2);
+    assert_eq!(front.len() + back.len(), len);
+    assert_eq!(back.chunks_exact(8).count(), (len - len / 2) / 8);
+}
+
+#[test]
+fn typed_arena_test() {
+    use crate::typed_arena::TypedArena;
+    use core::cell::Cell;
+    use core::mem;
+
+    struct Noisy<'a> {
+        id: u32,
+        order: &'a Cell<u32>,
+        sum: &'a Cell<u32>,
+    }
+
+    impl Drop for Noisy<'_> {
+        fn drop(&mut self) {
+            // each drop must see the ids in allocation order
+            assert_eq!(self.order.get(), self.id);
+            self.order.set(self.id + 1);
+            self.sum.set(self.sum.get() + self.id);
+        }
+    }
+
+    let order = Cell::new(0);
+    let sum = Cell::new(0);
+    let mut rt_memory = vec![0u8; 256];
+    let mut arena = TypedArena::from_unique_slice(&mut rt_memory);
+    let capacity = arena.capacity();
+
+    let first = arena.alloc(Noisy {
+        id: 0,
+        order: &order,
+        sum: &sum,
+    });
+    assert_eq!(first.id, 0);
+    let rest = arena.alloc_extend((1..4).map(|id| Noisy {
+        id,
+        order: &order,
+        sum: &sum,
+    }));
+    assert_eq!(rest.len(), 3);
+    assert_eq!(arena.len(), 4);
+
+    arena.reset();
+    assert_eq!(sum.get(), 6);
+    assert!(arena.is_empty());
+
+    order.set(0);
+    let filled = arena.alloc_extend((0..capacity as u32).map(|id| Noisy {
+        id,
+        order: &order,
+        sum: &sum,
+    }));
+    assert_eq!(filled.len(), capacity);
+    let unused = Cell::new(0);
+    let overflow = arena.try_alloc(Noisy {
+        id: 0,
+        order: &unused,
+        sum: &unused,
+    });
+    assert!(overflow.is_err());
+    mem::forget(overflow);
+
+    drop(arena);
+    assert_eq!(order.get(), capacity as u32);
+}
+
+#[test]
+fn drop_arena_test() {
+    use crate::drop_arena::DropArena;
+    use core::cell::RefCell;
+    use core::ptr;
+
+    struct Logged<'a>(u32, &'a RefCell<Vec<u32>>);
+
+    impl Drop for Logged<'_> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    let log = RefCell::new(Vec::new());
+    let mut rt_memory = vec![0u8; 512];
+    let mut arena = DropArena::from_unique_slice(&mut rt_memory);
+
+    let byte = arena.alloc(1u8);
+    let before = arena.used();
+    let word = arena.alloc(2u64);
+    assert!(ptr::from_mut(word).addr().is_multiple_of(8));
+    // plain values only pay for their own size and alignment padding
+    assert!(arena.used() - before <= 8 + 7);
+
+    let first = arena.alloc(Logged(1, &log));
+    let second = arena.alloc(Logged(2, &log));
+    *byte += *word as u8;
+    assert_eq!((*byte, first.0, second.0), (3, 1, 2));
+
+    arena.reset();
+    assert_eq!(*log.borrow(), [2, 1]);
+    assert_eq!(arena.used(), 0);
+
+    arena.alloc(Logged(3, &log));
+    drop(arena);
+    assert_eq!(*log.borrow(), [2, 1, 3]);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn frame_allocator_test() {
+    use crate::frame_allocator::{FrameAllocator, FrameHalf};
+
+    let mut rt_memory = vec![0u8; 1024];
+    let mut frames = FrameAllocator::from_unique_slice(&mut rt_memory);
+    let (even, odd) = frames.halves_mut();
+
+    let frame0 = even.begin_frame();
+    let mut zeroth: Vec<u32, &FrameHalf> = Vec::with_capacity_in(16, frame0);
+    zeroth.extend(0..16);
+
+    let frame1 = odd.begin_frame();
+    let mut first: Vec<u32, &FrameHalf> = Vec::with_capacity_in(32, frame1);
+    // frame 0 is still valid during frame 1
+    first.extend(zeroth.iter().map(|x| x * 2));
+    drop(zeroth);
+
+    let frame2 = even.begin_frame();
+    assert_eq!(frame2.used(), 0);
+    let mut second: Vec<u32, &FrameHalf> = Vec::with_capacity_in(8, frame2);
+    second.extend(first.iter().take(8).copied());
+    drop(first);
+
+    let frame3 = odd.begin_frame();
+    assert_eq!(frame3.used(), 0);
+    assert_eq!(second.iter().sum::<u32>(), 56);
+    drop(second);
+
+    let [even_peak, odd_peak] = frames.high_water_marks();
+    assert!(even_peak >= 16 * 4);
+    assert!(odd_peak >= 32 * 4);
+
+    // the halves take turns, each starting out empty
+    let mut frames = FrameAllocator::from_unique_slice(&mut rt_memory);
+    for round in 0..4u32 {
+        let frame = frames.begin_frame();
+        assert_eq!(frame.used(), 0);
+        let mut values: Vec<u32, &FrameHalf> = Vec::with_capacity_in(8 << round, frame);
+        values.extend(0..8 << round);
+        assert_eq!(values.len(), 8 << round);
+    }
+    let [even_peak, odd_peak] = frames.high_water_marks();
+    assert!(even_peak >= 32 * 4);
+    assert!(odd_peak >= 64 * 4);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn scratch_arenas_test() {
+    use crate::scratch::{Scratch, ScratchArenas};
+    use core::alloc::Layout;
+    use core::ptr;
+
+    fn squares_in<'o, 's, 'b>(arenas: &ScratchArenas<'b>, out: &'o Scratch<'s, 'b>, n: u32) -> Vec<u32, &'o Scratch<'s, 'b>> {
+        // temporaries must not land in the arena the result goes to
+        let scratch = arenas.get_scratch(&[out.arena()]).unwrap();
+        assert!(!ptr::eq(scratch.arena(), out.arena()));
+
+        let mut temp: Vec<u32, &Scratch> = Vec::with_capacity_in(n as usize, &scratch);
+        temp.extend(0..n);
+
+        let mut result = Vec::with_capacity_in(n as usize, out);
+        result.extend(temp.iter().map(|x| x * x));
+        result
+    }
+
+    let mut rt_memory = vec![0u8; 1024];
+    let arenas = ScratchArenas::from_unique_slice(&mut rt_memory);
+
+    let out = arenas.get_scratch(&[]).unwrap();
+    let squares = squares_in(&arenas, &out, 10);
+    assert_eq!(squares.iter().sum::<u32>(), 285);
+
+    let other = arenas.get_scratch(&[out.arena()]).unwrap();
+    // the temporaries of `squares_in` were rewound
+    assert_eq!(other.arena().used(), 0);
+    assert!(arenas.get_scratch(&[out.arena(), other.arena()]).is_none());
+
+    // an outer guard can't allocate while a nested guard on the same arena is alive
+    let nested = arenas.get_scratch(&[other.arena()]).unwrap();
+    assert!(ptr::eq(nested.arena(), out.arena()));
+    assert!(out.alloc(Layout::new::<u64>()).is_err());
+    drop(nested);
+    assert!(out.alloc(Layout::new::<u64>()).is_ok());
+
+    drop(squares);
+    drop(other);
+    drop(out);
+    assert_eq!(arenas.get_scratch(&[]).unwrap().arena().used(), 0);
+
+    // a guard dropped before a nested one is rewound along with it
+    let outer = arenas.get_scratch(&[]).unwrap();
+    outer.alloc(Layout::new::<[u64; 4]>()).unwrap();
+    let middle = arenas.get_scratch(&[]).unwrap();
+    middle.alloc(Layout::new::<[u64; 4]>()).unwrap();
+    let inner = arenas.get_scratch(&[]).unwrap();
+    inner.alloc(Layout::new::<[u64; 4]>()).unwrap();
+    drop(outer);
+    drop(inner);
+    assert_eq!(middle.arena().used(), 64);
+    assert!(middle.alloc(Layout::new::<u64>()).is_ok());
+    drop(middle);
+    assert_eq!(arenas.get_scratch(&[]).unwrap().arena().used(), 0);
+
+    // an arena with too many nested guards is skipped
+    let guards: Vec<Scratch> = (0..16).map(|_| arenas.get_scratch(&[]).unwrap()).collect();
+    let spare = arenas.get_scratch(&[]).unwrap();
+    assert!(!ptr::eq(spare.arena(), guards[0].arena()));
+    assert!(arenas.get_scratch(&[spare.arena()]).is_none());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn thread_arena_test() {
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use crate::thread_arena::{ThreadArena, thread_arena_scope, with_thread_arena};
+    extern crate std;
+    use std::thread;
+
+    let start = with_thread_arena(|arena| arena.used());
+
+    let mut kept: Vec<u32, ThreadArena> = Vec::with_capacity_in(4, ThreadArena::new());
+    kept.extend(0..4);
+
+    let sum = thread_arena_scope(|arena| {
+        let mut temp: Vec<u64, &SingleThreadedSliceAllocator> = Vec::with_capacity_in(128, arena);
+        temp.extend(0..128);
+
+        // growing a handle-allocated vector inside the scope pins its new block
+        kept.extend(4..64);
+        temp.iter().sum::<u64>()
+    });
+    assert_eq!(sum, 8128);
+    assert_eq!(kept.iter().sum::<u32>(), 2016);
+    assert!(with_thread_arena(|arena| arena.used()) >= start + 64 * 4);
+
+    drop(kept);
+    thread_arena_scope(|_| {});
+
+    // every thread gets its own arena
+    let other = thread::spawn(|| with_thread_arena(|arena| arena.used())).join().unwrap();
+    assert_eq!(other, 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn thread_cached_heap_test() {
+    use crate::thread_cache::ThreadCachedHeap;
+    use alloc::boxed::Box;
+    use core::alloc::{Allocator, GlobalAlloc, Layout};
+    extern crate std;
+    use std::sync::mpsc;
+    use std::thread;
+
+    let mut rt_memory = vec![0u8; 256 * 1024];
+    let heap = ThreadCachedHeap::from_unique_slice(&mut rt_memory);
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel::<Box<[u64; 4], &ThreadCachedHeap>>();
+
+        for worker in 0..4u64 {
+            let sender = sender.clone();
+            let heap = &heap;
+            scope.spawn(move || {
+                for i in 0..256 {
+                    let local = Box::new_in([worker, i, 0, 0], heap);
+                    sender.send(Box::new_in([worker, i, 1, 1], heap)).unwrap();
+                    assert_eq!(local[..2], [worker, i]);
+                }
+            });
+        }
+        drop(sender);
+
+        // every block is freed on a thread other than the one that cached it
+        scope.spawn(move || {
+            let mut total = 0;
+            for block in receiver {
+                assert_eq!(block[2..], [1, 1]);
+                total += 1;
+            }
+            assert_eq!(total, 4 * 256);
+        });
+    });
+
+    let large = Layout::from_size_align(4096, 64).unwrap();
+    unsafe {
+        let ptr = GlobalAlloc::alloc(&heap, large);
+        assert!(!ptr.is_null());
+        assert!(ptr.addr().is_multiple_of(64));
+        ptr.write_bytes(0xCD, large.size());
+        GlobalAlloc::dealloc(&heap, ptr, large);
+    }
+
+    let empty = ThreadCachedHeap::new();
+    assert!(empty.allocate(Layout::new::<u64>()).is_err());
+
+    // short-lived threads hand their caches back, so a small heap serves any amount of them
+    let mut small_memory = vec![0u8; 4096];
+    let small = ThreadCachedHeap::from_unique_slice(&mut small_memory);
+    for i in 0..256u64 {
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let block = Box::new_in([i; 2], &small);
+                assert_eq!(*block, [i; 2]);
+                drop(block);
+                small.flush_thread_cache();
+            });
+        });
+    }
+    let most = Layout::from_size_align(3584, 16).unwrap();
+    unsafe { small.deallocate(small.allocate(most).unwrap().cast(), most) };
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[allow(clippy::cast_ptr_alignment)]
+fn lock_free_pool_test() {
+    use crate::lock_free_pool::LockFreePool;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::ptr;
+    extern crate std;
+    use std::thread;
+
+    static mut POOL_MEMORY: [u8; 64 * 33] = [0; 64 * 33];
+    // Safety: the buffer is only accessed through the pool.
+    static POOL: LockFreePool<'static> =
+        unsafe { LockFreePool::from_raw_parts((&raw mut POOL_MEMORY).cast::<u8>(), 64 * 33, Layout::new::<[u64; 8]>()) };
+
+    assert!(POOL.capacity() >= 32);
+    assert_eq!(POOL.block_layout(), Layout::new::<[u64; 8]>());
+
+    thread::scope(|scope| {
+        for worker in 0..4u64 {
+            scope.spawn(move || {
+                let layout = Layout::new::<[u64; 8]>();
+                for round in 0..1000 {
+                    let mut blocks = [ptr::null_mut::<u64>(); 8];
+                    for block in &mut blocks {
+                        *block = unsafe { GlobalAlloc::alloc(&POOL, layout) }.cast::<u64>();
+                        assert!(!block.is_null());
+                        unsafe { block.write_bytes(0, 8) };
+                        unsafe { block.write(worker << 32 | round) };
+                    }
+                    // no other thread was handed the same block in the meantime
+                    for block in blocks {
+                        assert_eq!(unsafe { block.read() }, worker << 32 | round);
+                        unsafe { GlobalAlloc::dealloc(&POOL, block.cast(), layout) };
+                    }
+                }
+            });
+        }
+    });
+
+    let mut memory = [0u8; 256];
+    let pool = LockFreePool::from_unique_slice(&mut memory, Layout::new::<u32>());
+    let mut held = 0;
+    while pool.alloc_block(Layout::new::<u32>()).is_ok() {
+        held += 1;
+    }
+    assert_eq!(held, pool.capacity());
+    assert!(pool.alloc_block(Layout::new::<u64>()).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn locked_allocator_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::locked_allocator::{CriticalSection, CriticalSectionLock, LockedAllocator, SpinLock, StdMutexLock};
+    use crate::slice_allocator::StackAllocator;
+    use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+    use core::panic::AssertUnwindSafe;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    extern crate std;
+    use std::panic;
+    use std::thread;
+
+    static IN_SECTION: AtomicBool = AtomicBool::new(false);
+    struct FakeInterrupts;
+    unsafe impl CriticalSection for FakeInterrupts {
+        type State = bool;
+
+        fn acquire() -> bool {
+            IN_SECTION.swap(true, Ordering::SeqCst)
+        }
+
+        unsafe fn release(state: bool) {
+            IN_SECTION.store(state, Ordering::SeqCst);
+        }
+    }
+
+    let mut rt_memory = vec![0u8; 64 * 1024];
+    let heap = LockedAllocator::new(SpinLock::new(), ExperimentalAllocator::from_unique_slice(&mut rt_memory));
+
+    thread::scope(|scope| {
+        for worker in 0..4u64 {
+            let heap = &heap;
+            scope.spawn(move || {
+                for i in 0..200 {
+                    let mut values = Vec::with_capacity_in(4, heap);
+                    values.extend([worker, i, worker, i]);
+                    let boxed = Box::new_in(values.iter().sum::<u64>(), heap);
+                    assert_eq!(*boxed, 2 * (worker + i));
+                }
+            });
+        }
+    });
+    // the heap only aligns blocks to 8 bytes, so it refuses anything stricter
+    assert!(heap.allocate(Layout::from_size_align(64, 64).unwrap()).is_err());
+    assert!(heap.allocate(Layout::from_size_align(64, 8).unwrap()).is_ok());
+
+    let mut rt_memory = vec![0u8; 1024];
+    let stack = StackAllocator::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_slice(&mut rt_memory));
+    let heap = LockedAllocator::new(StdMutexLock::new(), stack);
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    let boxed = Box::new_in([7u32; 4], &heap);
+                    assert_eq!(*boxed, [7; 4]);
+                }
+            });
+        }
+    });
+    assert_eq!(heap.into_inner().pos(), 0);
+
+    let mut rt_memory = vec![0u8; 1024];
+    let heap = LockedAllocator::new(
+        CriticalSectionLock::<FakeInterrupts>::new(),
+        ExperimentalAllocator::from_unique_slice(&mut rt_memory),
+    );
+    heap.with_locked(|_| assert!(IN_SECTION.load(Ordering::SeqCst)));
+    assert!(!IN_SECTION.load(Ordering::SeqCst));
+    let boxed = Box::new_in(42u64, &heap);
+    assert_eq!(*boxed, 42);
+
+    // re-entering the section must not hand out the allocator twice
+    let nested = panic::catch_unwind(AssertUnwindSafe(|| heap.with_locked(|_| heap.with_locked(|_| ()))));
+    assert!(nested.is_err());
+    assert!(!IN_SECTION.load(Ordering::SeqCst));
+    drop(boxed);
+}
+
+#[test]
+fn async_allocator_test() {
+    use crate::async_allocator::AsyncAllocator;
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use alloc::boxed::Box;
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::alloc::Layout;
+    use core::array;
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{Context, Poll, Waker};
+
+    struct CountingWaker(AtomicUsize);
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let wake_counts: [_; 3] = array::from_fn(|_| Arc::new(CountingWaker(AtomicUsize::new(0))));
+    let wakers = wake_counts.clone().map(Waker::from);
+    let woken = |task: usize| wake_counts[task].0.load(Ordering::SeqCst);
+
+    let mut rt_memory = vec![0u8; 1024];
+    let heap = AsyncAllocator::new(ExperimentalAllocator::from_unique_slice(&mut rt_memory));
+
+    let big = Layout::from_size_align(512, 8).unwrap();
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let held = heap.try_alloc(big).unwrap();
+
+    // task 0 can't fit and waits, task 1 would fit but queues behind it
+    let mut first = pin!(heap.alloc_async(big));
+    let mut second = pin!(heap.alloc_async(small));
+    assert!(first.as_mut().poll(&mut Context::from_waker(&wakers[0])).is_pending());
+    assert!(second.as_mut().poll(&mut Context::from_waker(&wakers[1])).is_pending());
+    assert!(heap.has_waiters());
+    assert!(heap.try_alloc(small).is_err());
+
+    // cancelling a waiter in the middle of the queue leaves the others in place
+    {
+        let mut cancelled = pin!(heap.alloc_async(small));
+        assert!(cancelled.as_mut().poll(&mut Context::from_waker(&wakers[2])).is_pending());
+    }
+
+    unsafe { heap.dealloc(held.cast(), big) };
+    assert_eq!(woken(0), 1);
+    assert_eq!(woken(1), 0);
+
+    let Poll::Ready(first_block) = first.as_mut().poll(&mut Context::from_waker(&wakers[0])) else {
+        panic!("the front waiter should get the freed memory");
+    };
+    // the front waiter passes the wakeup on once it is served
+    assert_eq!(woken(1), 1);
+    assert!(second.as_mut().poll(&mut Context::from_waker(&wakers[1])).is_ready());
+    assert!(!heap.has_waiters());
+    assert_eq!(woken(2), 0);
+
+    // a cancelled front waiter hands its turn to the next one
+    let mut front = Box::pin(heap.alloc_async(big));
+    assert!(front.as_mut().poll(&mut Context::from_waker(&wakers[0])).is_pending());
+    {
+        let mut next = pin!(heap.alloc_async(big));
+        assert!(next.as_mut().poll(&mut Context::from_waker(&wakers[2])).is_pending());
+        drop(front);
+        assert_eq!(woken(2), 1);
+    }
+    assert!(!heap.has_waiters());
+    unsafe { heap.dealloc(first_block.cast(), big) };
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn budgeted_test() {
+    use crate::budgeted::{BudgetError, Budgeted};
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::locked_allocator::{ExclusiveAllocator, LockedAllocator, SpinLock};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 4096];
+    let heap = LockedAllocator::new(SpinLock::new(), ExperimentalAllocator::from_unique_slice(&mut rt_memory));
+    let root = Budgeted::new(&heap, 256, 8);
+    let audio = root.child(200, usize::MAX);
+    let network = root.child(200, 2);
+
+    let chunk = Layout::from_size_align(100, 8).unwrap();
+    let a = audio.try_alloc(chunk).unwrap();
+    let b = audio.try_alloc(chunk).unwrap();
+    assert_eq!(audio.try_alloc(chunk), Err(BudgetError::QuotaExceeded));
+
+    // the network budget has room, but the parent doesn't
+    assert_eq!(network.try_alloc(chunk), Err(BudgetError::QuotaExceeded));
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let c = network.try_alloc(small).unwrap();
+    assert_eq!((network.used_bytes(), network.live_allocs()), (16, 1));
+    assert_eq!((root.used_bytes(), root.live_allocs()), (216, 3));
+
+    unsafe {
+        audio.dealloc(a.cast(), chunk);
+        audio.dealloc(b.cast(), chunk);
+    }
+    assert_eq!((audio.used_bytes(), audio.live_allocs()), (0, 0));
+
+    // the allocation count limit is separate from the byte limit
+    let d = network.try_alloc(small).unwrap();
+    assert_eq!(network.try_alloc(small), Err(BudgetError::QuotaExceeded));
+    unsafe {
+        network.dealloc(c.cast(), small);
+        network.dealloc(d.cast(), small);
+    }
+
+    // a budget larger than the heap reports the heap failing
+    let greedy = root.child(usize::MAX, usize::MAX);
+    let unbounded = Budgeted::new(&heap, usize::MAX, usize::MAX);
+    assert_eq!(
+        unbounded.try_alloc(Layout::from_size_align(8192, 8).unwrap()),
+        Err(BudgetError::OutOfMemory)
+    );
+    assert_eq!(unbounded.used_bytes(), 0);
+
+    let mut values = Vec::new_in(&greedy);
+    values.extend(0..16u32);
+    assert!(greedy.allocate(Layout::from_size_align(256, 8).unwrap()).is_err());
+    drop(values);
+    assert_eq!((root.used_bytes(), root.live_allocs()), (0, 0));
+
+    // an exclusive allocator can be budgeted directly and shared afterwards
+    let mut rt_memory = vec![0u8; 4096];
+    let mut exclusive = Budgeted::new(ExperimentalAllocator::from_unique_slice(&mut rt_memory), 256, 8);
+    let a = exclusive.alloc_exclusive(chunk).unwrap();
+    let b = exclusive.alloc_exclusive(chunk).unwrap();
+    assert!(exclusive.alloc_exclusive(chunk).is_err());
+    unsafe { exclusive.dealloc_exclusive(a.cast(), chunk) };
+    assert_eq!((exclusive.used_bytes(), exclusive.live_allocs()), (100, 1));
+
+    let shared = LockedAllocator::new(SpinLock::new(), exclusive);
+    let boxed = Box::new_in([1u64; 8], &shared);
+    assert_eq!(shared.with_locked(|budget| budget.used_bytes()), 164);
+    drop(boxed);
+    unsafe { shared.with_locked(|budget| budget.dealloc_exclusive(b.cast(), chunk)) };
+    assert_eq!(shared.into_inner().used_bytes(), 0);
+}
+
+#[test]
+fn reclaiming_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::reclaim::{Priority, ReclaimHook, Reclaiming};
+    use alloc::vec::Vec;
+    use core::alloc::Layout;
+    use core::cell::{Cell, RefCell};
+    use core::ptr::NonNull;
+
+    struct Cache<'h, 'm> {
+        heap: Cell<Option<&'h Reclaiming<'h, ExperimentalAllocator<'m>>>>,
+        blocks: RefCell<Vec<NonNull<u8>>>,
+        flushes: Cell<usize>,
+    }
+
+    impl ReclaimHook for Cache<'_, '_> {
+        fn reclaim(&self, _needed: usize) -> usize {
+            self.flushes.set(self.flushes.get() + 1);
+            let heap = self.heap.get().unwrap();
+            let mut freed = 0;
+            for block in self.blocks.borrow_mut().drain(..) {
+                unsafe { heap.dealloc(block, ENTRY) };
+                freed += ENTRY.size();
+            }
+            freed
+        }
+    }
+
+    const ENTRY: Layout = Layout::new::<[u64; 16]>();
+
+    let mut rt_memory = vec![0u8; 2048];
+    let cache = Cache {
+        heap: Cell::new(None),
+        blocks: RefCell::new(Vec::new()),
+        flushes: Cell::new(0),
+    };
+    let heap = Reclaiming::new(
+        ExperimentalAllocator::from_unique_slice(&mut rt_memory),
+        Layout::from_size_align(256, 8).unwrap(),
+    );
+    let nothing_to_free = |_| 0;
+    assert!(heap.register_hook(&nothing_to_free).is_ok());
+    assert!(heap.register_hook(&cache).is_ok());
+    cache.heap.set(Some(&heap));
+    assert_eq!(heap.reserve_capacity(), 256);
+
+    // fill the heap with cache entries
+    while let Ok(block) = heap.alloc(ENTRY, Priority::Normal) {
+        cache.blocks.borrow_mut().push(block.cast());
+        if cache.flushes.get() > 0 {
+            break;
+        }
+    }
+    assert_eq!(cache.flushes.get(), 1);
+
+    // the flush made room for a large allocation
+    let large = Layout::from_size_align(1024, 8).unwrap();
+    let large_block = heap.alloc(large, Priority::Normal).unwrap();
+    assert_eq!(cache.flushes.get(), 1);
+
+    let mut live = Vec::new();
+    while let Ok(block) = heap.alloc(ENTRY, Priority::Normal) {
+        live.push(block);
+    }
+    assert!(cache.blocks.borrow().is_empty());
+
+    // only high-priority allocations may use the reserve
+    assert!(heap.alloc(ENTRY, Priority::Normal).is_err());
+    let report = heap.alloc(ENTRY, Priority::High).unwrap();
+    assert_eq!(heap.reserve_used(), ENTRY.size());
+    unsafe { heap.dealloc(report.cast(), ENTRY) };
+    assert_eq!(heap.reserve_used(), 0);
+
+    // a zero-size block at the end of the reserve would be freed to the inner allocator
+    let report = heap.alloc(Layout::from_size_align(256, 1).unwrap(), Priority::High).unwrap();
+    assert!(heap.alloc(Layout::new::<()>(), Priority::High).is_err());
+    unsafe { heap.dealloc(report.cast(), Layout::from_size_align(256, 1).unwrap()) };
+
+    for block in live {
+        unsafe { heap.dealloc(block.cast(), ENTRY) };
+    }
+    unsafe { heap.dealloc(large_block.cast(), large) };
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn stats_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::locked_allocator::ExclusiveAllocator;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use crate::stats::Stats;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 256];
+    let alloc = Stats::new(unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) });
+
+    let byte = Box::new_in(1u8, &alloc);
+    let word = Box::new_in(2u64, &alloc);
+    let mut values = Vec::new_in(&alloc);
+    values.extend(0..8u32);
+    values.push(8);
+    let stats = alloc.snapshot();
+    assert_eq!((stats.allocs, stats.reallocs, stats.frees, stats.failures), (3, 1, 0, 0));
+    assert_eq!(stats.current_bytes, 1 + 8 + 16 * 4);
+    assert_eq!(stats.histogram[..7], [1, 0, 0, 1, 0, 1, 1]);
+    // the u64 is aligned past the byte
+    assert_eq!(stats.padding_bytes, 7);
+
+    // fill the buffer until it fails, to see how full it got
+    while alloc.allocate(Layout::new::<[u64; 4]>()).is_ok() {}
+    drop((byte, word, values));
+    let stats = alloc.snapshot();
+    assert_eq!(stats.failures, 1);
+    assert_eq!(stats.frees, 3);
+    // the block abandoned by the vector's grow isn't counted
+    assert_eq!(stats.peak_bytes, 73 + 4 * 32);
+
+    let mut rt_memory = vec![0u8; 1024];
+    let mut heap = Stats::new(ExperimentalAllocator::from_unique_slice(&mut rt_memory));
+    let layout = Layout::new::<[u8; 100]>();
+    let block = heap.alloc_exclusive(layout).unwrap();
+    unsafe { heap.dealloc_exclusive(block.cast(), layout) };
+    let stats = heap.snapshot();
+    assert_eq!((stats.allocs, stats.frees, stats.current_bytes, stats.peak_bytes), (1, 1, 0, 100));
+    assert_eq!(stats.histogram[6], 1);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn tagged_allocator_test() {
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use crate::tagged::{TagUsage, TaggedAllocator};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    const UNTAGGED: usize = 0;
+    const AUDIO: usize = 1;
+    const NETWORK: usize = 2;
+
+    let mut rt_memory = vec![0u8; 1024];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = TaggedAllocator::new(inner, ["untagged", "audio", "network"]);
+
+    let samples = heap.with_tag(AUDIO, || {
+        let mut samples = Vec::with_capacity_in(16, &heap);
+        samples.extend(0..16i16);
+        samples
+    });
+    let packet = Box::new_in([0u8; 64], heap.tagged(NETWORK));
+    let header = Box::new_in(7u32, &heap);
+
+    assert_eq!(heap.usage(AUDIO).live_bytes, 32);
+    assert_eq!(heap.usage(NETWORK).live_bytes, 64);
+    assert_eq!(heap.usage(UNTAGGED).live_bytes, 4);
+
+    // nested scopes on different wrappers keep their own tags
+    let mut other_memory = vec![0u8; 256];
+    let other_inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut other_memory) };
+    let other = TaggedAllocator::new(other_inner, ["untagged", "scratch"]);
+    heap.with_tag(AUDIO, || {
+        other.with_tag(1, || {
+            drop(Box::new_in(1u64, &heap));
+            drop(Box::new_in(1u64, &other));
+        });
+        drop(Box::new_in(1u64, &heap));
+    });
+    assert_eq!((heap.usage(AUDIO).allocs, other.usage(1).allocs), (3, 1));
+
+    // blocks keep their tag when they grow, and are credited back to it wherever they
+    // are freed
+    let samples = heap.with_tag(NETWORK, || {
+        let mut samples = samples;
+        samples.reserve_exact(16);
+        samples
+    });
+    assert_eq!((heap.usage(AUDIO).live_bytes, heap.usage(NETWORK).live_bytes), (64, 64));
+    // growing doesn't count as another allocation
+    assert_eq!(heap.usage(AUDIO).allocs, 3);
+    heap.with_tag(NETWORK, || drop(samples));
+    drop(packet);
+    drop(header);
+    assert_eq!(
+        heap.report(),
+        [
+            TagUsage {
+                name: "untagged",
+                live_bytes: 0,
+                peak_bytes: 4,
+                allocs: 1,
+            },
+            TagUsage {
+                name: "audio",
+                live_bytes: 0,
+                peak_bytes: 64,
+                allocs: 3,
+            },
+            TagUsage {
+                name: "network",
+                live_bytes: 0,
+                peak_bytes: 64,
+                allocs: 1,
+            },
+        ]
+    );
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn observed_allocator_test() {
+    use crate::observer::{AllocObserver, Observed};
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+    use core::cell::RefCell;
+    use core::panic::Location;
+    use core::ptr::NonNull;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Event {
+        Alloc(usize, u32),
+        Dealloc(usize, u32),
+        Realloc(usize, usize),
+        Failure(usize),
+    }
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<Event>>);
+
+    impl AllocObserver for Recorder {
+        fn on_alloc(&self, _ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+            self.0.borrow_mut().push(Event::Alloc(layout.size(), location.line()));
+        }
+
+        fn on_dealloc(&self, _ptr: NonNull<u8>, layout: Layout, location: &'static Location<'static>) {
+            self.0.borrow_mut().push(Event::Dealloc(layout.size(), location.line()));
+        }
+
+        fn on_realloc(
+            &self,
+            _old_ptr: NonNull<u8>,
+            old_layout: Layout,
+            _new_ptr: NonNull<u8>,
+            new_layout: Layout,
+            _: &'static Location<'static>,
+        ) {
+            self.0.borrow_mut().push(Event::Realloc(old_layout.size(), new_layout.size()));
+        }
+
+        fn on_failure(&self, layout: Layout, _location: &'static Location<'static>) {
+            self.0.borrow_mut().push(Event::Failure(layout.size()));
+        }
+    }
+
+    let mut rt_memory = vec![0u8; 128];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let recorder = Recorder::default();
+    let heap = Observed::new(inner, (&recorder, ()));
+
+    let layout = Layout::new::<[u8; 16]>();
+    let block = heap.allocate(layout).unwrap();
+    let alloc_line = line!() - 1;
+    unsafe { heap.deallocate(block.cast(), layout) };
+    let dealloc_line = line!() - 1;
+    assert!(heap.allocate(Layout::new::<[u8; 256]>()).is_err());
+
+    let mut values = Vec::with_capacity_in(2, &heap);
+    values.extend([1u32, 2, 3]);
+    drop(values);
+
+    let events = recorder.0.take();
+    assert_eq!(
+        events[..3],
+        [Event::Alloc(16, alloc_line), Event::Dealloc(16, dealloc_line), Event::Failure(256)]
+    );
+    assert!(matches!(events[3], Event::Alloc(8, _)));
+    assert_eq!(events[4], Event::Realloc(8, 16));
+    assert!(matches!(events[5], Event::Dealloc(16, _)));
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn trace_replay_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::observer::Observed;
+    use crate::slice_allocator::{SingleThreadedSliceAllocator, StackAllocator};
+    use crate::stats::Stats;
+    use crate::trace::{self, TraceOp, TraceRecord, TraceRecorder, TraceRing};
+    use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 256];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = Observed::new(inner, TraceRecorder::new(TraceRing::<4>::new()));
+
+    let block = heap.allocate(Layout::new::<[u64; 4]>()).unwrap();
+    let mut values = Vec::with_capacity_in(2, &heap);
+    values.extend([1u32, 2, 3]);
+    drop(values);
+    unsafe { heap.deallocate(block.cast(), Layout::new::<[u64; 4]>()) };
+    assert!(heap.allocate(Layout::new::<[u8; 512]>()).is_err());
+
+    let ring = heap.observer().sink();
+    assert_eq!(ring.dropped(), 2);
+    let records: Vec<_> = ring.records().collect();
+    let ops: Vec<_> = records.iter().map(|record| record.op).collect();
+    assert_eq!(ops, [TraceOp::Grow, TraceOp::Dealloc, TraceOp::Dealloc, TraceOp::Failure][..]);
+    assert_eq!(records[3].size, 512);
+
+    // the encoding round-trips and stops at a truncated record
+    let mut bytes: Vec<u8> = records.iter().flat_map(|record| record.encode()).collect();
+    bytes.pop();
+    assert!(trace::parse_trace(&bytes).eq(records[..3].iter().copied()));
+
+    let mut rt_memory = vec![0u8; 4096];
+    let ring = TraceRing::<16>::new();
+    {
+        let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+        let heap = Observed::new(inner, TraceRecorder::new(&ring));
+        let mut values = Vec::with_capacity_in(4, &heap);
+        values.extend(0..64u32);
+        let small = Vec::<u8, _>::with_capacity_in(32, &heap);
+        drop(values);
+        drop(small);
+    }
+    assert_eq!(ring.dropped(), 0);
+
+    let mut rt_memory = vec![0u8; 4096];
+    let mut stack = StackAllocator::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_slice(&mut rt_memory));
+    let report = trace::replay(&mut stack, ring.records());
+    assert_eq!(report.failures, 0);
+    assert_eq!(report.peak_live_bytes, 256 + 32);
+    assert!(report.fragmentation() > 0.0);
+
+    let mut rt_memory = vec![0u8; 200];
+    let mut experimental = ExperimentalAllocator::from_unique_slice(&mut rt_memory);
+    let report = trace::replay(&mut experimental, ring.records());
+    assert!(report.failures > 0);
+    assert_eq!(report.ops, ring.records().count());
+
+    // a block grown in place is still moved and freed in the replay, with its new alignment
+    let record = |op, align, ptr, size, new_align, new_size| TraceRecord {
+        op,
+        align,
+        new_align,
+        ptr,
+        size,
+        new_ptr: if op == TraceOp::Grow { ptr } else { 0 },
+        new_size,
+    };
+    let in_place = [
+        record(TraceOp::Alloc, 1, 0x1000, 8, 1, 0),
+        record(TraceOp::Grow, 1, 0x1000, 8, 16, 32),
+        record(TraceOp::Dealloc, 16, 0x1000, 32, 1, 0),
+    ];
+    assert!(in_place.iter().all(|record| TraceRecord::decode(&record.encode()) == Some(*record)));
+
+    let mut rt_memory = vec![0u8; 256];
+    let stack = StackAllocator::from_unaligned_generic_buffer(UnalignedGenericBuffer::from_unique_slice(&mut rt_memory));
+    let mut stats = Stats::new(stack);
+    let report = trace::replay(&mut stats, in_place);
+    assert_eq!((report.failures, report.peak_live_bytes), (0, 8 + 32));
+    let snapshot = stats.snapshot();
+    assert_eq!((snapshot.allocs, snapshot.frees, snapshot.current_bytes), (2, 2, 0));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn heap_profile_test() {
+    use crate::observer::Observed;
+    use crate::profile::HeapProfiler;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use alloc::format;
+    use alloc::string::String;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 1024];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = Observed::new(inner, HeapProfiler::new(2));
+
+    let small = heap.allocate(Layout::new::<[u8; 16]>()).unwrap();
+    let small_line = line!() - 1;
+    let large = heap.allocate(Layout::new::<[u8; 100]>()).unwrap();
+    let large_line = line!() - 1;
+    unsafe { heap.deallocate(small.cast(), Layout::new::<[u8; 16]>()) };
+    unsafe { heap.deallocate(large.cast(), Layout::new::<[u8; 100]>()) };
+    heap.observer().take_snapshot();
+
+    let snapshots = heap.observer().snapshots();
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!((snapshots[0].time, snapshots[0].total_bytes), (2, 116));
+    assert_eq!(snapshots[0].sites.len(), 2);
+    assert_eq!(
+        (snapshots[0].sites[0].bytes, snapshots[0].sites[0].location.line()),
+        (100, large_line)
+    );
+    assert_eq!(
+        (snapshots[0].sites[1].bytes, snapshots[0].sites[1].location.line()),
+        (16, small_line)
+    );
+    assert_eq!((snapshots[1].total_bytes, snapshots[2].total_bytes), (0, 0));
+    assert!(snapshots[1].sites.is_empty());
+
+    let mut massif = Vec::new();
+    heap.observer().write_massif(&mut massif, "heap_profile_test").unwrap();
+    let massif = String::from_utf8(massif).unwrap();
+    assert!(massif.starts_with("desc: (none)\ncmd: heap_profile_test\ntime_unit: calls\n"));
+    assert_eq!(massif.matches("heap_tree=peak").count(), 1);
+    assert!(massif.contains(&format!(" n0: 100 0x0: ??? (src/tests.rs:{large_line}:")));
+
+    let mut json = Vec::new();
+    heap.observer().write_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with(r#"{"time_unit":"calls","snapshots":[{"time":2,"total_bytes":116,"sites":[{"file":"src/tests.rs","#));
+    assert!(json.ends_with("{\"time\":4,\"total_bytes\":0,\"sites\":[]}]}\n"));
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn leak_tracker_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::leak_check::{LeakAction, LeakTracker, LiveAlloc};
+    use crate::locked_allocator::ExclusiveAllocator;
+    use crate::observer::Observed;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use alloc::vec::Vec;
+    use core::alloc::Layout;
+    use core::mem;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static LEAKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+    fn report(alloc: &LiveAlloc) {
+        LEAKED_BYTES.fetch_add(alloc.layout.size(), Ordering::Relaxed);
+    }
+
+    let mut rt_memory = vec![0u8; 256];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = Observed::new(inner, LeakTracker::<2>::new(LeakAction::Report(report)));
+
+    {
+        let _scope = heap.observer().scope();
+        let mut values = Vec::with_capacity_in(1, &heap);
+        values.extend([1u32, 2]);
+        assert_eq!(values.len(), 2);
+    }
+    assert_eq!(LEAKED_BYTES.load(Ordering::Relaxed), 0);
+
+    let mut forgotten = Vec::with_capacity_in(1, &heap);
+    forgotten.extend([1u32, 2, 3]);
+    let leaked: Vec<_> = heap.observer().live().collect();
+    assert_eq!(leaked.len(), 1);
+    assert_eq!((leaked[0].layout.size(), leaked[0].seq), (16, 1));
+    {
+        let scope = heap.observer().scope();
+        let kept = Vec::<u8, _>::with_capacity_in(20, &heap);
+        let _untracked = Vec::<u8, _>::with_capacity_in(30, &heap);
+        assert_eq!(scope.live().count(), 1);
+        mem::forget(kept);
+    }
+    assert_eq!(LEAKED_BYTES.load(Ordering::Relaxed), 20);
+    assert_eq!(heap.observer().untracked(), 1);
+
+    mem::forget(forgotten);
+    drop(heap);
+    assert_eq!(LEAKED_BYTES.load(Ordering::Relaxed), 20 + 20 + 16);
+
+    let mut rt_memory = vec![0u8; 256];
+    let mut heap = Observed::new(
+        ExperimentalAllocator::from_unique_slice(&mut rt_memory),
+        LeakTracker::<4>::new(LeakAction::Panic),
+    );
+    let block = heap.alloc_exclusive(Layout::new::<u64>()).unwrap();
+    unsafe { heap.dealloc_exclusive(block.cast(), Layout::new::<u64>()) };
+    assert_eq!(heap.observer().check(), 0);
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn red_zone_test() {
+    use crate::experimental_allocator::ExperimentalAllocator;
+    use crate::locked_allocator::ExclusiveAllocator;
+    use crate::red_zone::{RED_ZONE_PATTERN, RedZone};
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 1024];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = RedZone::<_, 4>::new(inner);
+
+    let mut values = Vec::with_capacity_in(1, &heap);
+    values.extend([1u64, 2, 3]);
+    assert_eq!(values, [1, 2, 3]);
+    assert!(heap.check_all().is_ok());
+
+    let layout = Layout::from_size_align(10, 64).unwrap();
+    let block = heap.allocate(layout).unwrap().cast::<u8>();
+    assert_eq!(block.addr().get() % 64, 0);
+    let line = line!() - 2;
+    unsafe {
+        assert_eq!(block.sub(64).read(), RED_ZONE_PATTERN);
+        block.add(11).write(7);
+    }
+    let corruption = heap.check_all().unwrap_err();
+    assert_eq!((corruption.ptr, corruption.offset, corruption.found), (block, 11, 7));
+    assert_eq!(corruption.alloc.unwrap().location.line(), line);
+
+    unsafe { block.add(11).write(RED_ZONE_PATTERN) };
+    unsafe { block.sub(3).write(0) };
+    assert_eq!(heap.check_all().unwrap_err().offset, -3);
+    unsafe { block.sub(3).write(RED_ZONE_PATTERN) };
+    unsafe { heap.deallocate(block, layout) };
+    drop(values);
+    assert!(heap.check_all().is_ok());
+
+    let mut rt_memory = vec![0u8; 256];
+    let mut heap = RedZone::<_, 1>::new(ExperimentalAllocator::from_unique_slice(&mut rt_memory));
+    let block = heap.alloc_exclusive(Layout::new::<[u8; 8]>()).unwrap().cast::<u8>();
+    unsafe { block.add(8).write(0) };
+    assert_eq!(heap.check_all().unwrap_err().offset, 8);
+    unsafe { block.add(8).write(RED_ZONE_PATTERN) };
+    unsafe { heap.dealloc_exclusive(block, Layout::new::<[u8; 8]>()) };
+}
+
+#[test]
+fn poison_policy_test() {
+    use crate::poison::{ALLOC_POISON, FREE_POISON, PoisonPolicy};
+    use crate::slice_allocator::StackAllocator;
+    use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+    use core::alloc::Layout;
+
+    // fresh bytes are filled by the allocator's policy, not when the backing is made
+    let mut rt_memory = vec![0u8; 64];
+    let mem = UnalignedGenericBuffer::from_unique_slice(&mut rt_memory);
+    let mut alloc = StackAllocator::from_unaligned_generic_buffer(mem).with_poison_policy(PoisonPolicy::NONE);
+    let block = alloc.allocate(Layout::new::<[u8; 16]>()).unwrap().cast::<[u8; 16]>();
+    assert_eq!(unsafe { block.read() }, [0; 16]);
+    let mem = UnalignedGenericBuffer::from_unique_slice(&mut rt_memory);
+    let mut alloc = StackAllocator::from_unaligned_generic_buffer(mem).with_poison_policy(PoisonPolicy::FILL_ON_FREE);
+    let block = alloc.allocate(Layout::new::<[u8; 16]>()).unwrap().cast::<[u8; 16]>();
+    assert_eq!(unsafe { block.read() }, [FREE_POISON; 16]);
+    assert_eq!(rt_memory[16..], [0; 48]);
+
+    let mut rt_memory = vec![0u8; 64];
+    let mem = UnalignedGenericBuffer::from_unique_slice(&mut rt_memory);
+    let mut alloc = StackAllocator::from_unaligned_generic_buffer(mem).with_poison_policy(PoisonPolicy::FILL_BOTH.verifying());
+    let layout = Layout::new::<[u8; 16]>();
+    let block = alloc.allocate(layout).unwrap().cast::<[u8; 16]>();
+    assert_eq!(unsafe { block.read() }, [ALLOC_POISON; 16]);
+    unsafe { alloc.deallocate(block.cast(), layout) };
+    assert_eq!(unsafe { block.read() }, [FREE_POISON; 16]);
+    let block = alloc.allocate(layout).unwrap().cast::<[u8; 16]>();
+    unsafe { block.cast::<u8>().write(1) };
+    alloc.reset();
+    assert_eq!(unsafe { block.read() }, [FREE_POISON; 16]);
+
+    let mut rt_memory = vec![0u8; 64];
+    let mut alloc = UnalignedConstStackAllocator::from_unique_slice(&mut rt_memory).with_poison_policy(PoisonPolicy::NONE);
+    let block = alloc.alloc_const_unaligned(Layout::new::<[u8; 8]>());
+    unsafe { block.cast::<[u8; 8]>().write([1; 8]) };
+    unsafe { alloc.dealloc_const_unaligned(block, Layout::new::<[u8; 8]>()) };
+    assert_eq!(rt_memory[..8], [1; 8]);
+}
+
+#[test]
+#[should_panic = "written to after being freed"]
+fn poison_verify_on_reuse_test() {
+    use crate::poison::PoisonPolicy;
+    use crate::slice_allocator::StackAllocator;
+    use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
+    use core::alloc::Layout;
+
+    let mut rt_memory = vec![0u8; 64];
+    let mem = UnalignedGenericBuffer::from_unique_slice(&mut rt_memory);
+    let mut alloc = StackAllocator::from_unaligned_generic_buffer(mem).with_poison_policy(PoisonPolicy::FILL_ON_FREE.verifying());
+    let layout = Layout::new::<[u8; 16]>();
+    let block = alloc.allocate(layout).unwrap().cast::<u8>();
+    unsafe { alloc.deallocate(block, layout) };
+
+    // a write through the dangling pointer
+    unsafe { block.add(5).write(0) };
+    let _ = alloc.allocate(layout);
+}
+
+#[test]
+fn heap_walk_test() {
+    use crate::experimental_allocator::{ExperimentalAllocator, HeapSummary};
+    use core::alloc::Layout;
+
+    let mut rt_memory = vec![0u8; 512];
+    let mut heap = ExperimentalAllocator::from_unique_slice(&mut rt_memory);
+    let initial = heap.summary();
+    assert_eq!((initial.free_blocks, initial.largest_free_block), (1, initial.free_bytes));
+    assert_eq!(initial.min_ever_free_bytes, initial.free_bytes);
+    assert!(initial.fragmentation().abs() < f64::EPSILON);
+
+    let layout = Layout::new::<[u64; 4]>();
+    let blocks: Vec<_> = (0..3).map(|_| heap.alloc(layout).unwrap().cast::<u8>()).collect();
+    unsafe { heap.free(blocks[1], layout) };
+    let walk: Vec<_> = heap.blocks().collect();
+    assert_eq!(walk.iter().map(|block| block.allocated).collect::<Vec<_>>(), [true, false, true, false]);
+    assert!(walk.iter().zip(&walk[1..]).all(|(block, next)| block.addr + block.size == next.addr));
+    assert_eq!(walk.iter().map(|block| block.size).sum::<usize>(), initial.free_bytes);
+
+    let summary = heap.summary();
+    assert_eq!(summary.free_blocks, 2);
+    assert_eq!(summary.free_bytes, heap.free_bytes());
+    assert_eq!(summary.free_bytes, walk[1].size + walk[3].size);
+    assert_eq!(summary.largest_free_block, walk[3].size);
+    assert_eq!(summary.min_ever_free_bytes, initial.free_bytes - 3 * walk[0].size);
+    assert!(summary.fragmentation() > 0.0);
+
+    unsafe { heap.free(blocks[0], layout) };
+    unsafe { heap.free(blocks[2], layout) };
+    assert_eq!(
+        heap.summary(),
+        HeapSummary {
+            min_ever_free_bytes: summary.min_ever_free_bytes,
+            ..initial
+        }
+    );
+}
+
+#[test]
+fn heap_validate_test() {
+    use crate::experimental_allocator::{ExperimentalAllocator, HeapCorruption, HeapCorruptionKind};
+    use core::alloc::Layout;
+
+    let mut rt_memory = vec![0u8; 512];
+    let mut heap = ExperimentalAllocator::from_unique_slice(&mut rt_memory).with_validation(true);
+    assert_eq!(heap.validate(), Ok(()));
+
+    let layout = Layout::new::<[u64; 4]>();
+    let blocks: Vec<_> = (0..3).map(|_| heap.alloc(layout).unwrap().cast::<u8>()).collect();
+    unsafe { heap.free(blocks[1], layout) };
+    assert_eq!(heap.validate(), Ok(()));
+
+    // the size word sits right before the block, with the allocated tag in its top bit
+    let size_word = unsafe { blocks[0].cast::<usize>().sub(1) };
+    let header = size_word.cast::<u8>().addr().get() - size_of::<usize>();
+    let tagged = unsafe { size_word.read() };
+    unsafe { size_word.write(tagged & (usize::MAX >> 1)) };
+    let missing = HeapCorruption {
+        block: header,
+        kind: HeapCorruptionKind::MissingFromFreeList,
+    };
+    assert_eq!(heap.validate(), Err(missing));
+    assert!(alloc::format!("{missing}").contains("not on the free list"));
+
+    unsafe { size_word.write(1 << (usize::BITS - 1)) };
+    assert_eq!(heap.validate().unwrap_err().kind, HeapCorruptionKind::BlockSize);
+    unsafe { size_word.write(tagged) };
+
+    let last = heap.blocks().last().unwrap();
+    let end_marker = unsafe { blocks[0].byte_add(last.addr + last.size - blocks[0].addr().get()) };
+    unsafe { end_marker.cast::<usize>().add(1).write(8) };
+    assert_eq!(heap.validate().unwrap_err().kind, HeapCorruptionKind::EndMarker);
+    unsafe { end_marker.cast::<usize>().add(1).write(0) };
+
+    let free_link = unsafe { blocks[1].cast::<usize>().sub(2) };
+    let next = unsafe { free_link.read() };
+    unsafe { free_link.write(free_link.addr().get()) };
+    assert_eq!(heap.validate().unwrap_err().kind, HeapCorruptionKind::FreeListCycle);
+    unsafe { free_link.write(header) };
+    assert_eq!(heap.validate().unwrap_err().kind, HeapCorruptionKind::FreeListOrder);
+    unsafe { free_link.write(next) };
+
+    unsafe { heap.free(blocks[0], layout) };
+    unsafe { heap.free(blocks[2], layout) };
+    assert_eq!(heap.validate(), Ok(()));
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn shadow_checked_test() {
+    use crate::lock_free_pool::LockFreePool;
+    use crate::shadow::{Misuse, ShadowChecked};
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use alloc::vec::Vec;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 1024];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = ShadowChecked::<_, 4>::new(inner);
+
+    let mut values = Vec::with_capacity_in(1, &heap);
+    values.extend([1u32, 2, 3]);
+    assert_eq!(heap.live().count(), 1);
+    drop(values);
+    assert_eq!(heap.live().count(), 0);
+
+    let layout = Layout::new::<[u32; 4]>();
+    let block = heap.allocate(layout).unwrap().cast::<u8>();
+    let line = line!() - 1;
+    let alloc = heap.check(block, layout).unwrap();
+    assert_eq!((alloc.ptr, alloc.location.line()), (block, line));
+
+    let misuse = heap.check(block, Layout::new::<[u32; 2]>()).unwrap_err();
+    assert!(matches!(misuse, Misuse::SizeMismatch { alloc: a, .. } if a == alloc));
+    let misuse = heap.check(block, Layout::new::<[u64; 2]>()).unwrap_err();
+    assert!(matches!(misuse, Misuse::AlignMismatch { alloc: a, .. } if a == alloc));
+    let interior = unsafe { block.add(4) };
+    assert_eq!(heap.check(interior, layout), Err(Misuse::Interior { ptr: interior, offset: 4, alloc }));
+    let past_end = unsafe { block.add(layout.size()) };
+    assert_eq!(heap.check(past_end, layout), Err(Misuse::Unknown { ptr: past_end }));
+
+    unsafe { heap.deallocate(block, layout) };
+    assert_eq!(heap.check(block, layout), Err(Misuse::DoubleFree { ptr: block, alloc }));
+    assert!(alloc::format!("{}", heap.check(block, layout).unwrap_err()).starts_with("double free"));
+
+    // a zero-size block shares its address with the next one, and isn't tracked
+    let empty = heap.allocate(Layout::new::<[u32; 0]>()).unwrap().cast::<u8>();
+    let block = heap.allocate(layout).unwrap().cast::<u8>();
+    assert_eq!(empty, block);
+    assert_eq!(heap.check(block, layout).map(|alloc| alloc.layout), Ok(layout));
+    unsafe { heap.deallocate(block, layout) };
+    unsafe { heap.deallocate(empty, Layout::new::<[u32; 0]>()) };
+
+    let blocks: Vec<_> = (0..4).map(|_| heap.allocate(layout).unwrap()).collect();
+    assert!(heap.allocate(layout).is_err());
+    let empty = heap.allocate(Layout::new::<()>()).unwrap();
+    unsafe { heap.deallocate(empty.cast(), Layout::new::<()>()) };
+    for block in blocks {
+        unsafe { heap.deallocate(block.cast(), layout) };
+    }
+
+    // blocks are handed out at the checked size, even where the inner allocator returns more
+    let mut rt_memory = vec![0u8; 256];
+    let heap = ShadowChecked::<_, 4>::new(LockFreePool::from_unique_slice(&mut rt_memory, Layout::new::<[u64; 4]>()));
+    let odd = Layout::from_size_align(13, 1).unwrap();
+    assert_eq!(heap.inner().alloc_block(odd).unwrap().len(), 32);
+    let block = heap.allocate(odd).unwrap();
+    assert_eq!(block.len(), odd.size());
+    unsafe { heap.deallocate(block.cast(), odd) };
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+#[should_panic = "doesn't match allocation"]
+fn shadow_checked_mismatch_test() {
+    use crate::shadow::ShadowChecked;
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use core::alloc::{Allocator, Layout};
+
+    let mut rt_memory = vec![0u8; 256];
+    let inner = unsafe { SingleThreadedSliceAllocator::from_unique_slice(&mut rt_memory) };
+    let heap = ShadowChecked::<_, 4>::new(inner);
+    let block = heap.allocate(Layout::new::<[u8; 32]>()).unwrap();
+    unsafe { heap.deallocate(block.cast(), Layout::new::<[u8; 16]>()) };
+}
+
+#[cfg(all(
+    feature = "std",
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+))]
+#[test]
+fn guard_page_test() {
+    use crate::guard_page::{GuardPageAllocator, GuardSide};
+    use crate::slice_allocator::SingleThreadedSliceAllocator;
+    use core::alloc::{Allocator, Layout};
+    use std::fs;
+
+    // returns whether the page holding `addr` is mapped, and whether it is accessible
+    let page_access = |addr: usize| {
+        let maps = fs::read_to_string("/proc/self/maps").unwrap();
+        maps.lines().find_map(|line| {
+            let (range, rest) = line.split_once(' ')?;
+            let (start, end) = range.split_once('-')?;
+            let range = usize::from_str_radix(start, 16).ok()?..usize::from_str_radix(end, 16).ok()?;
+            range.contains(&addr).then(|| !rest.starts_with("---"))
+        })
+    };
+
+    let heap = GuardPageAllocator::new(GuardSide::End, 1);
+    let page = heap.page_size();
+    let layout = Layout::new::<[u64; 3]>();
+    let block = heap.allocate_zeroed(layout).unwrap().cast::<u8>();
+    let end = block.addr().get() + layout.size();
+    assert_eq!(end % page, 0);
+    assert_eq!(unsafe { block.cast::<[u64; 3]>().read() }, [0; 3]);
+    assert_eq!((page_access(end - 1), page_access(end)), (Some(true), Some(false)));
+
+    let mut text = Vec::new_in(&heap);
+    text.extend_from_slice(b"guarded");
+    text.extend_from_slice(b" pages");
+    assert_eq!(text, b"guarded pages");
+    drop(text);
+
+    unsafe { heap.deallocate(block, layout) };
+    assert_eq!(page_access(end - 1), Some(false));
+    let other = heap.allocate(layout).unwrap().cast::<u8>();
+    unsafe { heap.deallocate(other, layout) };
+    assert_eq!(page_access(end - 1), None);
+
+    let heap = GuardPageAllocator::new(GuardSide::Start, 0);
+    let block = heap.allocate(Layout::new::<u8>()).unwrap().cast::<u8>();
+    assert_eq!(block.addr().get() % page, 0);
+    assert_eq!(page_access(block.addr().get() - 1), Some(false));
+    unsafe { heap.deallocate(block, Layout::new::<u8>()) };
+    assert_eq!(page_access(block.addr().get()), None);
+
+    let backing = heap.backing(100).unwrap();
+    let start = backing.as_ptr().addr();
+    let inner = unsafe { SingleThreadedSliceAllocator::from_backing_allocation(backing) };
+    let mut values = Vec::with_capacity_in(4, &inner);
+    values.extend([1u32, 2, 3, 4]);
+    assert_eq!(values, [1, 2, 3, 4]);
+    assert_eq!(page_access(start - 1), Some(false));
+}
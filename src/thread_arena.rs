@@ -0,0 +1,134 @@
+use crate::slice_allocator::SingleThreadedSliceAllocator;
+use alloc::boxed::Box;
+use core::alloc::AllocError;
+use core::alloc::Allocator;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use std::thread_local;
+
+/// Size of the chunk each thread's arena takes from the system allocator.
+pub const THREAD_ARENA_CAPACITY: usize = 1 << 20;
+
+struct ThreadArenaState {
+    arena: SingleThreadedSliceAllocator<'static>,
+    /// End of the highest live block handed out through [`ThreadArena`]. Scoped rewinds
+    /// never go below it, as those blocks aren't tied to any scope.
+    pinned: Cell<usize>,
+    /// Number of blocks handed out through [`ThreadArena`] that haven't been freed yet.
+    live: Cell<usize>,
+    chunk: NonNull<[MaybeUninit<u8>]>,
+}
+
+impl ThreadArenaState {
+    fn new() -> Self {
+        let chunk = Box::into_raw(Box::<[u8]>::new_uninit_slice(THREAD_ARENA_CAPACITY));
+        // Safety: the chunk is only reachable through this thread's state, and is freed
+        // in `Drop` only once nothing can point into it.
+        let arena = unsafe { SingleThreadedSliceAllocator::from_unique_uninit_slice(&mut *chunk) };
+
+        Self {
+            arena,
+            pinned: Cell::new(0),
+            live: Cell::new(0),
+            chunk: unsafe { NonNull::new_unchecked(chunk) },
+        }
+    }
+}
+
+impl Drop for ThreadArenaState {
+    fn drop(&mut self) {
+        // blocks handed out through `ThreadArena` may still be referenced by other
+        // thread-locals, so the chunk is leaked while any of them is live. Anything else
+        // was allocated through a reference that can't outlive `with_thread_arena`.
+        if self.live.get() == 0 {
+            drop(unsafe { Box::from_raw(self.chunk.as_ptr()) });
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_ARENA: ThreadArenaState = ThreadArenaState::new();
+}
+
+/// Runs `f` with the current thread's arena, creating it on first use.
+///
+/// Anything allocated through the arena reference stays allocated until the thread exits;
+/// use [`thread_arena_scope`] to reclaim it when `f` returns.
+///
+/// # Panics
+///
+/// Panics if called while the thread is being torn down.
+#[inline]
+pub fn with_thread_arena<R, F>(f: F) -> R
+where
+    F: FnOnce(&SingleThreadedSliceAllocator<'_>) -> R,
+{
+    THREAD_ARENA.with(|state| f(&state.arena))
+}
+
+/// Runs `f` with the current thread's arena and rewinds it when `f` returns.
+///
+/// Blocks allocated through [`ThreadArena`] in the meantime are kept, along with
+/// everything below them.
+///
+/// # Panics
+///
+/// Panics if called while the thread is being torn down.
+#[inline]
+pub fn thread_arena_scope<R, F>(f: F) -> R
+where
+    F: FnOnce(&SingleThreadedSliceAllocator<'_>) -> R,
+{
+    THREAD_ARENA.with(|state| {
+        let mark = state.arena.used();
+        let result = f(&state.arena);
+
+        // Safety: allocations made through the reference given to `f` can't outlive
+        // the call, and `ThreadArena` blocks all lie below `pinned`.
+        unsafe { state.arena.rewind(mark.max(state.pinned.get())) };
+        result
+    })
+}
+
+/// A zero-sized handle to the current thread's arena.
+///
+/// It isn't `Send`, so collections using it stay on the thread they allocated on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadArena {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadArena {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { _not_send: PhantomData }
+    }
+}
+
+unsafe impl Allocator for ThreadArena {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        THREAD_ARENA
+            .try_with(|state| {
+                let block = state.arena.allocate(layout)?;
+                state.pinned.set(state.pinned.get().max(state.arena.used()));
+                state.live.set(state.live.get() + 1);
+                Ok(block)
+            })
+            .unwrap_or(Err(AllocError))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // a failed access means the thread is exiting and the chunk is leaked anyway
+        let _ = THREAD_ARENA.try_with(|state| {
+            unsafe { state.arena.deallocate(ptr, layout) };
+            state.pinned.set(state.pinned.get().min(state.arena.used()));
+            state.live.set(state.live.get() - 1);
+        });
+    }
+}
@@ -0,0 +1,379 @@
+use crate::backing_alloc::BackingAllocation;
+use crate::experimental_allocator::ExperimentalAllocator;
+use core::alloc::AllocError;
+use core::alloc::Allocator;
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Mutex;
+use std::thread_local;
+
+/// Block sizes served from the per-thread caches. Larger or over-aligned requests go
+/// to the shared heap directly.
+const SIZE_CLASSES: [usize; 6] = [16, 32, 64, 128, 256, 512];
+const CLASS_ALIGN: usize = 16;
+/// Blocks moved between a thread cache and the shared heap per lock acquisition.
+const BATCH: usize = 8;
+/// A thread cache holding more blocks of a class than this returns a batch to the heap.
+const CACHE_LIMIT: usize = 4 * BATCH;
+
+/// Amount of threads that get a cache at once. Threads started while all slots are taken
+/// use the shared heap until one is released.
+pub const MAX_CACHED_THREADS: usize = 64;
+
+const NO_OWNER: u32 = u32::MAX;
+const NO_CLASS: u32 = u32::MAX;
+const NO_SLOT: usize = usize::MAX;
+
+#[repr(C)]
+struct BlockHeader {
+    raw: *mut u8,
+    owner: u32,
+    class: u32,
+}
+
+const HEADER_SIZE: usize = size_of::<BlockHeader>().next_multiple_of(CLASS_ALIGN);
+
+/// Set while a thread holds the cache slot.
+static CLAIMED_SLOTS: [AtomicBool; MAX_CACHED_THREADS] = [const { AtomicBool::new(false) }; MAX_CACHED_THREADS];
+
+/// The cache slot of a thread, released when the thread exits.
+struct SlotClaim(Cell<usize>);
+
+impl Drop for SlotClaim {
+    fn drop(&mut self) {
+        let slot = self.0.get();
+        if slot != NO_SLOT {
+            CLAIMED_SLOTS[slot].store(false, Ordering::Release);
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_SLOT: SlotClaim = const { SlotClaim(Cell::new(NO_SLOT)) };
+}
+
+/// Returns the cache slot of the current thread, claiming a free one if it has none.
+fn thread_slot() -> Option<usize> {
+    THREAD_SLOT
+        .try_with(|claim| {
+            if claim.0.get() == NO_SLOT
+                && let Some(slot) = CLAIMED_SLOTS
+                    .iter()
+                    .position(|claimed| claimed.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok())
+            {
+                claim.0.set(slot);
+            }
+            claim.0.get()
+        })
+        .ok()
+        .filter(|&slot| slot != NO_SLOT)
+}
+
+fn class_of(layout: Layout) -> Option<usize> {
+    if layout.align() > CLASS_ALIGN {
+        return None;
+    }
+    SIZE_CLASSES.iter().position(|&class_size| layout.size() <= class_size)
+}
+
+/// Layout of the heap block backing a user block of `size` bytes aligned to `align`.
+const fn raw_layout(size: usize, align: usize) -> Result<Layout, AllocError> {
+    let Some(padded) = size.checked_add(HEADER_SIZE + align - 1) else {
+        return Err(AllocError);
+    };
+    match Layout::from_size_align(padded, 1) {
+        Ok(layout) => Ok(layout),
+        Err(_) => Err(AllocError),
+    }
+}
+
+/// User pointers are always aligned to [`CLASS_ALIGN`], and so is their header.
+#[allow(clippy::cast_ptr_alignment)]
+const unsafe fn header_of(user: *mut u8) -> *mut BlockHeader {
+    unsafe { user.sub(HEADER_SIZE).cast::<BlockHeader>() }
+}
+
+/// Free blocks are linked through their first word.
+#[allow(clippy::cast_ptr_alignment)]
+const fn link_of(user: *mut u8) -> *mut *mut u8 {
+    user.cast::<*mut u8>()
+}
+
+struct SharedHeap<'buf>(ExperimentalAllocator<'buf>);
+
+impl SharedHeap<'_> {
+    fn alloc_block(&mut self, size: usize, align: usize, owner: u32, class: u32) -> Result<NonNull<u8>, AllocError> {
+        let raw = self.0.alloc(raw_layout(size, align)?).map_err(|_| AllocError)?.cast::<u8>();
+        let user = raw.as_ptr().map_addr(|addr| (addr + HEADER_SIZE).next_multiple_of(align));
+
+        let header = BlockHeader {
+            raw: raw.as_ptr(),
+            owner,
+            class,
+        };
+        unsafe { header_of(user).write(header) };
+        Ok(unsafe { NonNull::new_unchecked(user) })
+    }
+
+    unsafe fn free_block(&mut self, user: NonNull<u8>, size: usize, align: usize) {
+        let raw = unsafe { (*header_of(user.as_ptr())).raw };
+        if let Ok(layout) = raw_layout(size, align) {
+            unsafe { self.0.free(NonNull::new_unchecked(raw), layout) };
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FreeList {
+    head: *mut u8,
+    len: usize,
+}
+
+impl FreeList {
+    const EMPTY: Self = Self {
+        head: ptr::null_mut(),
+        len: 0,
+    };
+
+    const unsafe fn push(&mut self, user: *mut u8) {
+        unsafe { link_of(user).write(self.head) };
+        self.head = user;
+        self.len += 1;
+    }
+
+    unsafe fn pop(&mut self) -> Option<NonNull<u8>> {
+        let user = NonNull::new(self.head)?;
+        self.head = unsafe { link_of(user.as_ptr()).read() };
+        self.len -= 1;
+        Some(user)
+    }
+}
+
+struct ThreadSlot {
+    /// Only touched by the thread owning the slot.
+    cache: UnsafeCell<[FreeList; SIZE_CLASSES.len()]>,
+    /// Blocks of this slot freed by other threads, linked through their first word.
+    remote_frees: AtomicPtr<u8>,
+}
+
+// Safety: `cache` is only accessed by the owning thread, `remote_frees` is atomic.
+unsafe impl Sync for ThreadSlot {}
+
+impl ThreadSlot {
+    const fn new() -> Self {
+        Self {
+            cache: UnsafeCell::new([FreeList::EMPTY; SIZE_CLASSES.len()]),
+            remote_frees: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn push_remote(&self, user: *mut u8) {
+        let mut head = self.remote_frees.load(Ordering::Relaxed);
+        loop {
+            unsafe { link_of(user).write(head) };
+            match self
+                .remote_frees
+                .compare_exchange_weak(head, user, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// A thread-safe heap with a small size-class cache per thread in front of a shared,
+/// locked [`ExperimentalAllocator`].
+///
+/// Each thread refills and drains its cache in batches, so the lock is taken once per
+/// [`BATCH`] blocks. A block freed by a thread other than the one it was cached for is
+/// pushed onto the owner's lock-free remote-free queue, which the owner drains on its
+/// next allocation.
+///
+/// Up to [`MAX_CACHED_THREADS`] threads of the process hold a cache slot at once, and
+/// release it when they exit. The blocks cached for an exited thread and those freed to
+/// its remote-free queue go to the next thread taking its slot; until then they stay
+/// unavailable, unless the thread returned them with
+/// [`flush_thread_cache`](Self::flush_thread_cache) before exiting.
+pub struct ThreadCachedHeap<'buf> {
+    heap: Mutex<Option<SharedHeap<'buf>>>,
+    slots: [ThreadSlot; MAX_CACHED_THREADS],
+}
+
+impl<'buf> ThreadCachedHeap<'buf> {
+    /// Creates a heap without memory, see [`init`](Self::init). Usable in statics.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        ThreadCachedHeap {
+            heap: Mutex::new(None),
+            slots: [const { ThreadSlot::new() }; MAX_CACHED_THREADS],
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
+        ThreadCachedHeap::from_backing_allocation(BackingAllocation::from_unique_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        let heap = ThreadCachedHeap::new();
+        heap.init(mem);
+        heap
+    }
+
+    /// Hands the heap its memory. Does nothing if it already has some.
+    #[inline]
+    pub fn init(&self, mem: BackingAllocation<'buf>) {
+        let mut heap = self.heap.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if heap.is_none() {
+            *heap = Some(SharedHeap(ExperimentalAllocator::from_backing_allocation(mem)));
+        }
+    }
+
+    fn with_heap<R, F>(&self, f: F) -> Result<R, AllocError>
+    where
+        F: FnOnce(&mut SharedHeap<'buf>) -> Result<R, AllocError>,
+    {
+        let mut heap = self.heap.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        heap.as_mut().map_or(Err(AllocError), f)
+    }
+
+    /// Moves the blocks other threads freed for `slot` into its cache.
+    unsafe fn drain_remote_frees(&self, slot: usize, cache: &mut [FreeList; SIZE_CLASSES.len()]) {
+        let mut user = self.slots[slot].remote_frees.swap(ptr::null_mut(), Ordering::Acquire);
+        while !user.is_null() {
+            let next = unsafe { link_of(user).read() };
+            let class = unsafe { (*header_of(user)).class } as usize;
+            unsafe { cache[class].push(user) };
+            user = next;
+        }
+
+        for (class, list) in cache.iter_mut().enumerate() {
+            if list.len > CACHE_LIMIT {
+                unsafe { self.release_batch(class, list) };
+            }
+        }
+    }
+
+    /// Returns blocks of `list` to the heap until it's down to half the cache limit.
+    unsafe fn release_batch(&self, class: usize, list: &mut FreeList) {
+        let _ = self.with_heap(|heap| {
+            while list.len > CACHE_LIMIT / 2 {
+                let Some(user) = (unsafe { list.pop() }) else { break };
+                unsafe { heap.free_block(user, SIZE_CLASSES[class], CLASS_ALIGN) };
+            }
+            Ok(())
+        });
+    }
+
+    /// Returns the blocks cached for the current thread to the shared heap, along with
+    /// those other threads freed for it.
+    #[inline]
+    pub fn flush_thread_cache(&self) {
+        let Some(slot) = thread_slot() else { return };
+        let cache = unsafe { &mut *self.slots[slot].cache.get() };
+        unsafe { self.drain_remote_frees(slot, cache) };
+
+        let _ = self.with_heap(|heap| {
+            for (class, list) in cache.iter_mut().enumerate() {
+                while let Some(user) = unsafe { list.pop() } {
+                    unsafe { heap.free_block(user, SIZE_CLASSES[class], CLASS_ALIGN) };
+                }
+            }
+            Ok(())
+        });
+    }
+
+    unsafe fn alloc_cached(&self, slot: usize, class: usize) -> Result<NonNull<u8>, AllocError> {
+        let cache = unsafe { &mut *self.slots[slot].cache.get() };
+        if cache[class].len == 0 {
+            unsafe { self.drain_remote_frees(slot, cache) };
+        }
+
+        if cache[class].len == 0 {
+            self.with_heap(|heap| {
+                for _ in 0..BATCH {
+                    let Ok(user) = heap.alloc_block(SIZE_CLASSES[class], CLASS_ALIGN, slot as u32, class as u32) else {
+                        break;
+                    };
+                    unsafe { cache[class].push(user.as_ptr()) };
+                }
+                Ok(())
+            })?;
+        }
+
+        unsafe { cache[class].pop() }.ok_or(AllocError)
+    }
+}
+
+impl Default for ThreadCachedHeap<'_> {
+    #[inline]
+    fn default() -> Self {
+        ThreadCachedHeap::new()
+    }
+}
+
+unsafe impl Allocator for ThreadCachedHeap<'_> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let user = if let (Some(class), Some(slot)) = (class_of(layout), thread_slot()) {
+            unsafe { self.alloc_cached(slot, class)? }
+        } else {
+            let align = layout.align().max(CLASS_ALIGN);
+            self.with_heap(|heap| heap.alloc_block(layout.size(), align, NO_OWNER, NO_CLASS))?
+        };
+
+        Ok(NonNull::slice_from_raw_parts(user, layout.size()))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let header = unsafe { &*header_of(ptr.as_ptr()) };
+
+        if header.owner == NO_OWNER {
+            let align = layout.align().max(CLASS_ALIGN);
+            let _ = self.with_heap(|heap| {
+                unsafe { heap.free_block(ptr, layout.size(), align) };
+                Ok(())
+            });
+            return;
+        }
+
+        let owner = header.owner as usize;
+        if thread_slot() != Some(owner) {
+            self.slots[owner].push_remote(ptr.as_ptr());
+            return;
+        }
+
+        let class = header.class as usize;
+        let cache = unsafe { &mut *self.slots[owner].cache.get() };
+        unsafe { cache[class].push(ptr.as_ptr()) };
+        if cache[class].len > CACHE_LIMIT {
+            unsafe { self.release_batch(class, &mut cache[class]) };
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for ThreadCachedHeap<'_> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout).map_or(ptr::null_mut(), |block| block.cast::<u8>().as_ptr())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { self.deallocate(ptr, layout) };
+        }
+    }
+}
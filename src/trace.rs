@@ -0,0 +1,417 @@
+use crate::locked_allocator::ExclusiveAllocator;
+use crate::observer::AllocObserver;
+use alloc::collections::BTreeMap;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::cell::UnsafeCell;
+use core::panic::Location;
+use core::ptr::NonNull;
+
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, PoisonError};
+
+/// Size of one encoded [`TraceRecord`].
+pub const RECORD_SIZE: usize = 35;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TraceOp {
+    Alloc = 0,
+    Dealloc = 1,
+    Grow = 2,
+    Shrink = 3,
+    Failure = 4,
+}
+
+/// One allocator call of a trace.
+///
+/// For [`Grow`](TraceOp::Grow) and [`Shrink`](TraceOp::Shrink), `ptr`, `size` and `align`
+/// describe the old block and `new_ptr`, `new_size` and `new_align` the new one. Otherwise
+/// `new_ptr` and `new_size` are 0 and `new_align` is 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub op: TraceOp,
+    pub align: usize,
+    pub new_align: usize,
+    pub ptr: u64,
+    pub size: u64,
+    pub new_ptr: u64,
+    pub new_size: u64,
+}
+
+impl TraceRecord {
+    /// Encodes the record as the op, the log2 of both alignments and four little-endian
+    /// `u64`s.
+    #[inline]
+    #[must_use]
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0] = self.op as u8;
+        bytes[1] = self.align.trailing_zeros() as u8;
+        bytes[2] = self.new_align.trailing_zeros() as u8;
+        for (chunk, value) in bytes[3..]
+            .chunks_exact_mut(8)
+            .zip([self.ptr, self.size, self.new_ptr, self.new_size])
+        {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a record, or returns `None` if the op or an alignment is invalid.
+    #[inline]
+    #[must_use]
+    pub fn decode(bytes: &[u8; RECORD_SIZE]) -> Option<Self> {
+        let op = match bytes[0] {
+            0 => TraceOp::Alloc,
+            1 => TraceOp::Dealloc,
+            2 => TraceOp::Grow,
+            3 => TraceOp::Shrink,
+            4 => TraceOp::Failure,
+            _ => return None,
+        };
+        let align = 1usize.checked_shl(u32::from(bytes[1]))?;
+        let new_align = 1usize.checked_shl(u32::from(bytes[2]))?;
+
+        let mut words = bytes[3..].chunks_exact(8).map(|chunk| {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        });
+        let mut next = || words.next().unwrap_or(0);
+
+        Some(Self {
+            op,
+            align,
+            new_align,
+            ptr: next(),
+            size: next(),
+            new_ptr: next(),
+            new_size: next(),
+        })
+    }
+
+    #[inline]
+    fn layout(&self) -> Option<Layout> {
+        Layout::from_size_align(usize::try_from(self.size).ok()?, self.align).ok()
+    }
+
+    #[inline]
+    fn new_layout(&self) -> Option<Layout> {
+        Layout::from_size_align(usize::try_from(self.new_size).ok()?, self.new_align).ok()
+    }
+}
+
+/// Decodes a trace, stopping at the first invalid or incomplete record.
+#[inline]
+pub fn parse_trace(bytes: &[u8]) -> impl Iterator<Item = TraceRecord> + '_ {
+    bytes
+        .chunks_exact(RECORD_SIZE)
+        .map_while(|chunk| TraceRecord::decode(chunk.try_into().ok()?))
+}
+
+/// Where a [`TraceRecorder`] writes encoded records.
+pub trait TraceSink {
+    fn write_record(&self, record: &[u8; RECORD_SIZE]);
+}
+
+impl<S: TraceSink + ?Sized> TraceSink for &S {
+    #[inline]
+    fn write_record(&self, record: &[u8; RECORD_SIZE]) {
+        (**self).write_record(record);
+    }
+}
+
+/// Keeps the last `N` records in place, overwriting the oldest ones. It needs no allocator.
+pub struct TraceRing<const N: usize> {
+    records: UnsafeCell<[[u8; RECORD_SIZE]; N]>,
+    next: Cell<usize>,
+    written: Cell<usize>,
+}
+
+impl<const N: usize> TraceRing<N> {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            records: UnsafeCell::new([[0; RECORD_SIZE]; N]),
+            next: Cell::new(0),
+            written: Cell::new(0),
+        }
+    }
+
+    /// Returns how many records were overwritten before they could be read.
+    #[inline]
+    #[must_use]
+    pub const fn dropped(&self) -> usize {
+        self.written.get().saturating_sub(N)
+    }
+
+    /// Returns the kept records, oldest first.
+    #[inline]
+    pub fn records(&self) -> impl Iterator<Item = TraceRecord> + '_ {
+        let kept = self.written.get().min(N);
+        let first = (self.next.get() + N - kept) % N.max(1);
+        (0..kept).filter_map(move |i| {
+            // Safety: the ring isn't `Sync` and no record is borrowed while writing one.
+            let record = unsafe { (*self.records.get())[(first + i) % N] };
+            TraceRecord::decode(&record)
+        })
+    }
+}
+
+impl<const N: usize> Default for TraceRing<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TraceSink for TraceRing<N> {
+    #[inline]
+    fn write_record(&self, record: &[u8; RECORD_SIZE]) {
+        if N == 0 {
+            return;
+        }
+        let next = self.next.get();
+        unsafe { (*self.records.get())[next] = *record };
+        self.next.set((next + 1) % N);
+        self.written.set(self.written.get() + 1);
+    }
+}
+
+/// Appends records to a file through a buffered writer.
+#[cfg(feature = "std")]
+pub struct TraceFile {
+    writer: Mutex<BufWriter<File>>,
+}
+
+#[cfg(feature = "std")]
+impl TraceFile {
+    #[inline]
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self { writer: Mutex::new(BufWriter::new(File::create(path)?)) })
+    }
+
+    #[inline]
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner).flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl TraceSink for TraceFile {
+    /// Write errors are ignored, so tracing never makes an allocation fail.
+    #[inline]
+    fn write_record(&self, record: &[u8; RECORD_SIZE]) {
+        let _ = self.writer.lock().unwrap_or_else(PoisonError::into_inner).write_all(record);
+    }
+}
+
+/// An [`AllocObserver`] encoding every call into a [`TraceSink`]. Use it with
+/// [`Observed`](crate::observer::Observed) to trace an allocator.
+pub struct TraceRecorder<S> {
+    sink: S,
+}
+
+impl<S: TraceSink> TraceRecorder<S> {
+    #[inline]
+    #[must_use]
+    pub const fn new(sink: S) -> Self {
+        Self { sink }
+    }
+
+    #[inline]
+    pub const fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    #[inline]
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    #[inline]
+    fn record(&self, op: TraceOp, ptr: usize, layout: Layout, new_ptr: usize, new_layout: Option<Layout>) {
+        let record = TraceRecord {
+            op,
+            align: layout.align(),
+            new_align: new_layout.map_or(1, |layout| layout.align()),
+            ptr: ptr as u64,
+            size: layout.size() as u64,
+            new_ptr: new_ptr as u64,
+            new_size: new_layout.map_or(0, |layout| layout.size()) as u64,
+        };
+        self.sink.write_record(&record.encode());
+    }
+}
+
+impl<S: TraceSink> AllocObserver for TraceRecorder<S> {
+    #[inline]
+    fn on_alloc(&self, ptr: NonNull<u8>, layout: Layout, _location: &'static Location<'static>) {
+        self.record(TraceOp::Alloc, ptr.addr().get(), layout, 0, None);
+    }
+
+    #[inline]
+    fn on_dealloc(&self, ptr: NonNull<u8>, layout: Layout, _location: &'static Location<'static>) {
+        self.record(TraceOp::Dealloc, ptr.addr().get(), layout, 0, None);
+    }
+
+    #[inline]
+    fn on_realloc(
+        &self,
+        old_ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_ptr: NonNull<u8>,
+        new_layout: Layout,
+        _location: &'static Location<'static>,
+    ) {
+        let op = if new_layout.size() >= old_layout.size() {
+            TraceOp::Grow
+        } else {
+            TraceOp::Shrink
+        };
+        self.record(op, old_ptr.addr().get(), old_layout, new_ptr.addr().get(), Some(new_layout));
+    }
+
+    #[inline]
+    fn on_failure(&self, layout: Layout, _location: &'static Location<'static>) {
+        self.record(TraceOp::Failure, 0, layout, 0, None);
+    }
+}
+
+/// The outcome of replaying a trace with [`replay`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Records replayed, not counting recorded failures.
+    pub ops: usize,
+    /// Allocations and reallocations that failed during the replay.
+    pub failures: usize,
+    pub peak_live_bytes: usize,
+    /// Distance from the lowest block start to the highest block end ever handed out.
+    pub footprint_bytes: usize,
+}
+
+impl ReplayReport {
+    /// Returns the share of the footprint that was never live at the peak, from 0 to 1.
+    #[inline]
+    #[must_use]
+    pub fn fragmentation(&self) -> f64 {
+        if self.footprint_bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.peak_live_bytes as f64 / self.footprint_bytes as f64
+        }
+    }
+}
+
+struct ReplayState<'a, A: ExclusiveAllocator> {
+    alloc: &'a mut A,
+    /// Recorded address to replayed block and layout.
+    live: BTreeMap<u64, (NonNull<u8>, Layout)>,
+    live_bytes: usize,
+    lowest: usize,
+    highest: usize,
+    report: ReplayReport,
+}
+
+impl<A: ExclusiveAllocator> ReplayState<'_, A> {
+    fn place(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let Ok(block) = self.alloc.alloc_exclusive(layout) else {
+            self.report.failures += 1;
+            return None;
+        };
+        let start = block.cast::<u8>().addr().get();
+        self.lowest = self.lowest.min(start);
+        self.highest = self.highest.max(start + layout.size());
+        self.live_bytes += layout.size();
+        self.report.peak_live_bytes = self.report.peak_live_bytes.max(self.live_bytes);
+        Some(block.cast())
+    }
+
+    fn alloc(&mut self, recorded: u64, layout: Layout) {
+        if let Some(ptr) = self.place(layout) {
+            self.live.insert(recorded, (ptr, layout));
+        }
+    }
+
+    fn free(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.live_bytes -= layout.size();
+        unsafe { self.alloc.dealloc_exclusive(ptr, layout) };
+    }
+
+    fn dealloc(&mut self, recorded: u64) {
+        // blocks whose allocation failed in the replay are skipped
+        if let Some((ptr, layout)) = self.live.remove(&recorded) {
+            self.free(ptr, layout);
+        }
+    }
+
+    /// Moves the block recorded at `recorded` to a new one recorded at `new_recorded`,
+    /// which may be the same address.
+    fn realloc(&mut self, recorded: u64, new_recorded: u64, new_layout: Layout) {
+        let old = self.live.remove(&recorded);
+        match (self.place(new_layout), old) {
+            (Some(new_ptr), Some((ptr, layout))) => {
+                unsafe { ptr.copy_to_nonoverlapping(new_ptr, layout.size().min(new_layout.size())) };
+                self.free(ptr, layout);
+                self.live.insert(new_recorded, (new_ptr, new_layout));
+            }
+            (Some(new_ptr), None) => {
+                self.live.insert(new_recorded, (new_ptr, new_layout));
+            }
+            // the old block stands in for the new one, so it's freed with it
+            (None, Some(old)) => {
+                self.live.insert(new_recorded, old);
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn release_all(&mut self) {
+        while let Some((_, (ptr, layout))) = self.live.pop_first() {
+            unsafe { self.alloc.dealloc_exclusive(ptr, layout) };
+        }
+    }
+}
+
+/// Replays a trace against `alloc`, mapping recorded addresses to the replayed blocks.
+///
+/// A grow or shrink is replayed as a new allocation, a copy and freeing the old block, even
+/// if it was done in place when recorded.
+/// Recorded failures are skipped, and blocks still live at the end are freed.
+#[inline]
+pub fn replay<A: ExclusiveAllocator, I: IntoIterator<Item = TraceRecord>>(alloc: &mut A, trace: I) -> ReplayReport {
+    let mut state = ReplayState {
+        alloc,
+        live: BTreeMap::new(),
+        live_bytes: 0,
+        lowest: usize::MAX,
+        highest: 0,
+        report: ReplayReport::default(),
+    };
+
+    for record in trace {
+        if record.op == TraceOp::Failure {
+            continue;
+        }
+        state.report.ops += 1;
+
+        match (record.op, record.layout(), record.new_layout()) {
+            (TraceOp::Alloc, Some(layout), _) => state.alloc(record.ptr, layout),
+            (TraceOp::Dealloc, _, _) => state.dealloc(record.ptr),
+            (TraceOp::Grow | TraceOp::Shrink, _, Some(new_layout)) => state.realloc(record.ptr, record.new_ptr, new_layout),
+            _ => state.report.failures += 1,
+        }
+    }
+
+    state.release_all();
+    state.report.footprint_bytes = state.highest.saturating_sub(state.lowest);
+    state.report
+}
@@ -0,0 +1,135 @@
+use crate::aligned_raw_slice::AlignedMutRawSlice;
+use crate::backing_alloc::BackingAllocation;
+use core::cell::Cell;
+use core::mem::{MaybeUninit, size_of};
+use core::ptr;
+use core::slice;
+
+/// An arena of `T`s laid out contiguously in a [`BackingAllocation`].
+///
+/// Every `T` placed in the arena is dropped, in allocation order, on
+/// [`reset`](Self::reset) or when the arena itself is dropped.
+pub struct TypedArena<'buf, T> {
+    mem: AlignedMutRawSlice<'buf, T>,
+    len: Cell<usize>,
+}
+
+impl<'buf, T> TypedArena<'buf, T> {
+    #[inline]
+    #[must_use]
+    pub fn from_unique_slice(slice: &'buf mut [u8]) -> Self {
+        TypedArena::from_backing_allocation(BackingAllocation::from_unique_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn from_unique_uninit_slice(slice: &'buf mut [MaybeUninit<u8>]) -> Self {
+        TypedArena::from_backing_allocation(BackingAllocation::from_unique_uninit_slice(slice))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn from_backing_allocation(mem: BackingAllocation<'buf>) -> Self {
+        assert!(size_of::<T>() != 0, "ZST currently unsupported but possible to implement");
+        TypedArena {
+            mem: AlignedMutRawSlice::from_backing_allocation(mem),
+            len: Cell::new(0),
+        }
+    }
+
+    /// Returns the amount of `T`s the arena can hold in total.
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.mem.len()
+    }
+
+    /// Returns the amount of live `T`s in the arena.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Moves `value` into the arena, handing it back if the arena is full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_alloc(&self, value: T) -> Result<&mut T, T> {
+        let len = self.len.get();
+        if len >= self.capacity() {
+            return Err(value);
+        }
+
+        // Safety: len is in bounds, and the slot past the live values is never handed out.
+        let slot = unsafe { self.mem.as_ptr().cast_mut().add(len) };
+        unsafe { slot.write(value) };
+        self.len.set(len + 1);
+
+        Ok(unsafe { &mut *slot })
+    }
+
+    /// Moves `value` into the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, value: T) -> &mut T {
+        let Ok(slot) = self.try_alloc(value) else {
+            panic!("TypedArena is full")
+        };
+        slot
+    }
+
+    /// Moves every item of `iter` into the arena and returns them as one contiguous slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena runs out of space, or if `iter` allocates in this arena while
+    /// it is being consumed. Items moved in before the panic stay owned by the arena.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend<I>(&self, iter: I) -> &mut [T]
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let start = self.len.get();
+        let mut count = 0;
+
+        for value in iter {
+            assert!(self.len.get() == start + count, "iterator allocated in the arena it is extending");
+            let Ok(_) = self.try_alloc(value) else {
+                panic!("TypedArena is full")
+            };
+            count += 1;
+        }
+
+        // Safety: the values in [start, start + count) were just written and are contiguous.
+        unsafe { slice::from_raw_parts_mut(self.mem.as_ptr().cast_mut().add(start), count) }
+    }
+
+    /// Drops every live `T` in allocation order and makes the whole arena available again.
+    #[inline]
+    pub fn reset(&mut self) {
+        let len = self.len.replace(0);
+        let live = ptr::slice_from_raw_parts_mut(self.mem.as_mut_ptr(), len);
+
+        // Safety: the first `len` slots hold initialized values that are no longer borrowed,
+        // and `len` was cleared first so they can't be dropped twice.
+        unsafe { ptr::drop_in_place(live) };
+    }
+}
+
+impl<T> Drop for TypedArena<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
@@ -1,5 +1,6 @@
 use crate::const_alloc_panic;
 use crate::const_allocator_shared::AllocError;
+use crate::poison::PoisonPolicy;
 use crate::unaligned_generic_buffer::UnalignedGenericBuffer;
 use core::alloc::Layout;
 use core::mem::MaybeUninit;
@@ -9,6 +10,9 @@ use core::ptr::NonNull;
 pub struct UnalignedConstStackAllocator<'buffer> {
     buffer: UnalignedGenericBuffer<'buffer, u8>,
     pos: usize,
+    /// The highest position ever reached. Bytes between `pos` and it have been freed.
+    high_water: usize,
+    poison: PoisonPolicy,
 }
 
 impl<'alloc> UnalignedConstStackAllocator<'alloc> {
@@ -18,6 +22,8 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
         UnalignedConstStackAllocator {
             buffer: UnalignedGenericBuffer::from_unique_slice(slice),
             pos: 0,
+            high_water: 0,
+            poison: PoisonPolicy::DEFAULT,
         }
     }
 
@@ -27,9 +33,25 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
         UnalignedConstStackAllocator {
             buffer: UnalignedGenericBuffer::from_unique_uninit_slice(slice),
             pos: 0,
+            high_water: 0,
+            poison: PoisonPolicy::DEFAULT,
         }
     }
 
+    /// Replaces the [`PoisonPolicy::DEFAULT`] the allocator starts with.
+    #[inline]
+    #[must_use]
+    pub const fn with_poison_policy(mut self, policy: PoisonPolicy) -> Self {
+        self.poison = policy;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn poison_policy(&self) -> PoisonPolicy {
+        self.poison
+    }
+
     /// # Errors
     ///
     /// - If the requested layout is too large to fit in the underlying allocation
@@ -44,6 +66,8 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
         }
 
         let ptr = unsafe { self.buffer.as_unaligned_mut_ptr().add(self.pos) };
+
+        unsafe { self.poison_new_bytes(ptr, len, true) };
         self.pos += len;
 
         match NonNull::new(ptr) {
@@ -52,6 +76,31 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
         }
     }
 
+    /// Checks the `len` bytes at `ptr`, just past the position, before they are handed out
+    /// and fills them if `fill` is set. Fresh bytes get the free byte first, as if they had
+    /// been freed before. Moves the high water mark past them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the policy verifies reused bytes and one of them was overwritten.
+    #[inline]
+    const unsafe fn poison_new_bytes(&mut self, ptr: *mut u8, len: usize, fill: bool) {
+        // bytes below the high water mark were handed out and freed before
+        let reused = self.high_water.saturating_sub(self.pos);
+        let reused = if reused < len { reused } else { len };
+        if unsafe { self.poison.find_overwritten(ptr, reused) }.is_some() {
+            const_alloc_panic!("a freed byte was written to after being freed");
+        }
+        unsafe { self.poison.on_free(ptr.add(reused), len - reused) };
+        if fill {
+            unsafe { self.poison.on_alloc(ptr, len) };
+        }
+
+        if self.pos + len > self.high_water {
+            self.high_water = self.pos + len;
+        }
+    }
+
     #[inline]
     pub const fn alloc_const_unaligned(&mut self, layout: Layout) -> NonNull<u8> {
         match self.alloc_const_unaligned_fallible(layout) {
@@ -84,6 +133,8 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
         if offset + old_layout.size() == self.pos {
             // if new size is smaller, we can just update the position
             if new_layout.size() <= old_layout.size() {
+                let freed = old_layout.size() - new_layout.size();
+                unsafe { self.poison.on_free(ptr.as_ptr().add(new_layout.size()), freed) };
                 self.pos = offset + new_layout.size();
                 return Ok(ptr);
             }
@@ -91,6 +142,7 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
             // otherwise check if we have enough space
             let additional_space = new_layout.size() - old_layout.size();
             if self.pos + additional_space <= self.buffer.unaligned_len() {
+                unsafe { self.poison_new_bytes(ptr.as_ptr().add(old_layout.size()), additional_space, false) };
                 self.pos += additional_space;
                 return Ok(ptr);
             }
@@ -144,20 +196,7 @@ impl<'alloc> UnalignedConstStackAllocator<'alloc> {
 
         let offset = unsafe { ptr.as_ptr().offset_from_unsigned(self.buffer.as_unaligned_ptr()) };
 
-        if cfg!(debug_assertions) {
-            // rewrite the contents of the buffer to 0xAA for better debugging experience.
-            let debug_ptr = unsafe { self.buffer.as_unaligned_mut_ptr().add(offset) };
-
-            // ⚠️ debug_ptr may be unaligned!
-            // ⚠️ maybe UB: ptr::write_bytes(debug_ptr, 0xAA, size);
-
-            let mut i = 0;
-            while i < size {
-                let byte_ptr = unsafe { debug_ptr.add(i) };
-                unsafe { byte_ptr.write_unaligned(0xAA) };
-                i += 1;
-            }
-        }
+        unsafe { self.poison.on_free(self.buffer.as_unaligned_mut_ptr().add(offset), size) };
 
         // if the pointer is at the end of the buffer, we can just update the position
         if offset + size == self.pos {
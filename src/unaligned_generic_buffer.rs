@@ -1,5 +1,6 @@
 use crate::aligned_raw_slice::AlignedMutRawSlice;
 use crate::aligned_raw_slice::AlignedRawSlice;
+use crate::backing_alloc;
 use crate::backing_alloc::BackingAllocation;
 use crate::const_allocator_shared::next_aligned_addr;
 use core::marker::PhantomData;
@@ -157,4 +158,97 @@ impl<'buf, T> UnalignedGenericBuffer<'buf, T> {
         // Safety: as_raw_mut_slice returns an aligned mutable slice.
         unsafe { AlignedMutRawSlice::from_mut_raw_slice(self.as_mut_raw_slice()) }
     }
+
+    #[inline]
+    #[must_use]
+    pub const fn into_backing_allocation(self) -> BackingAllocation<'buf> {
+        self.mem
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn reborrow(&mut self) -> UnalignedGenericBuffer<'_, T> {
+        UnalignedGenericBuffer::from_backing_allocation(self.mem.reborrow())
+    }
+
+    /// Divides the buffer into two at `mid` unaligned `T`s, see
+    /// [`unaligned_len`](Self::unaligned_len).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > unaligned_len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at(self, mid: usize) -> (Self, Self) {
+        match self.split_at_checked(mid) {
+            Some(halves) => halves,
+            None => panic!("mid > unaligned_len"),
+        }
+    }
+
+    /// Divides the buffer into two at `mid` unaligned `T`s, returning `None` if
+    /// `mid > unaligned_len`.
+    #[inline]
+    #[must_use]
+    pub const fn split_at_checked(self, mid: usize) -> Option<(Self, Self)> {
+        if mid > self.unaligned_len() {
+            return None;
+        }
+
+        let (head, tail) = self.mem.split_at(mid * size_of::<T>());
+        Some((UnalignedGenericBuffer::from_backing_allocation(head), UnalignedGenericBuffer::from_backing_allocation(tail)))
+    }
+
+    /// Divides the buffer at the first byte offset that is properly aligned for a `U`,
+    /// see [`BackingAllocation::split_aligned_for`].
+    #[inline]
+    #[must_use]
+    pub fn split_aligned_for<U>(self) -> (Self, Self) {
+        let (head, tail) = self.mem.split_aligned_for::<U>();
+        (UnalignedGenericBuffer::from_backing_allocation(head), UnalignedGenericBuffer::from_backing_allocation(tail))
+    }
+
+    /// Splits the buffer into disjoint chunks of `chunk_size` unaligned `T`s.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    #[inline]
+    #[must_use]
+    pub const fn chunks_exact(self, chunk_size: usize) -> ChunksExact<'buf, T> {
+        ChunksExact {
+            inner: self.mem.chunks_exact(chunk_size * size_of::<T>()),
+            _marker: PhantomData,
+        }
+    }
 }
+
+pub struct ChunksExact<'buf, T> {
+    inner: backing_alloc::ChunksExact<'buf>,
+    _marker: PhantomData<&'buf mut T>,
+}
+
+impl<'buf, T> ChunksExact<'buf, T> {
+    /// Returns the bytes left over after all whole chunks, consuming the iterator.
+    #[inline]
+    #[must_use]
+    pub const fn into_remainder(self) -> UnalignedGenericBuffer<'buf, T> {
+        UnalignedGenericBuffer::from_backing_allocation(self.inner.into_remainder())
+    }
+}
+
+impl<'buf, T> Iterator for ChunksExact<'buf, T> {
+    type Item = UnalignedGenericBuffer<'buf, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(UnalignedGenericBuffer::from_backing_allocation)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for ChunksExact<'_, T> {}